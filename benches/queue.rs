@@ -0,0 +1,108 @@
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use job_sched::{Interval, Job, JobScheduler, ManualClock, Trigger};
+
+const JOB_COUNTS: [u64; 3] = [1_000, 10_000, 100_000];
+
+/// Jobs firing every 1 to 10 seconds, so that few are due at each millisecond.
+fn period(i: u64) -> Duration {
+    Duration::from_millis(1_000 + i * 7 % 9_000)
+}
+
+/// The trigger lookups ticking did before jobs were queued: every job's
+/// trigger is asked for its next event on each tick. Due jobs are only
+/// counted, not run, and none of the scheduler's own bookkeeping is done, so
+/// this is a lower bound on that cost rather than a like-for-like
+/// `JobScheduler::tick`.
+struct LinearScan {
+    jobs: Vec<(Interval, DateTime<Utc>)>,
+}
+
+impl LinearScan {
+    fn new(count: u64, now: DateTime<Utc>) -> LinearScan {
+        let jobs = (0..count)
+            .map(|i| (Interval::new(period(i)), now))
+            .collect();
+        LinearScan { jobs }
+    }
+
+    fn tick(&mut self, now: DateTime<Utc>) -> usize {
+        let mut ran = 0;
+        for (trigger, last_tick) in &mut self.jobs {
+            if trigger
+                .next_after(last_tick)
+                .is_some_and(|next| next <= now)
+            {
+                ran += 1;
+            }
+            *last_tick = now;
+        }
+        ran
+    }
+}
+
+fn scheduler(count: u64, clock: &ManualClock, wheel: bool) -> JobScheduler {
+    let mut scheduler = JobScheduler::new().with_clock(clock.clone());
+    if wheel {
+        scheduler = scheduler.with_timing_wheel(Duration::from_millis(1));
+    }
+    for i in 0..count {
+        scheduler.add(Job::new(Interval::new(period(i)), || {}));
+    }
+    // The first tick anchors every job.
+    scheduler.tick();
+    scheduler
+}
+
+fn tick(c: &mut Criterion) {
+    let mut group = c.benchmark_group("tick");
+    for count in JOB_COUNTS {
+        group.bench_with_input(
+            BenchmarkId::new("linear_scan", count),
+            &count,
+            |b, &count| {
+                let mut now = DateTime::UNIX_EPOCH;
+                let mut scan = LinearScan::new(count, now);
+                b.iter(|| {
+                    now += chrono::Duration::milliseconds(1);
+                    scan.tick(now)
+                })
+            },
+        );
+        for (name, wheel) in [("heap", false), ("timing_wheel", true)] {
+            group.bench_with_input(BenchmarkId::new(name, count), &count, |b, &count| {
+                let clock = ManualClock::new(DateTime::UNIX_EPOCH);
+                let mut scheduler = scheduler(count, &clock, wheel);
+                b.iter(|| {
+                    clock.advance(chrono::Duration::milliseconds(1));
+                    scheduler.tick();
+                })
+            });
+        }
+    }
+    group.finish();
+}
+
+fn add_remove(c: &mut Criterion) {
+    let mut group = c.benchmark_group("add_remove");
+    for count in JOB_COUNTS {
+        for (name, wheel) in [("heap", false), ("timing_wheel", true)] {
+            group.bench_with_input(BenchmarkId::new(name, count), &count, |b, &count| {
+                let clock = ManualClock::new(DateTime::UNIX_EPOCH);
+                let mut scheduler = scheduler(count, &clock, wheel);
+                let mut i = 0;
+                b.iter(|| {
+                    i += 1;
+                    let id = scheduler.add(Job::new(Interval::new(period(i)), || {}));
+                    scheduler.remove(id)
+                })
+            });
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, tick, add_remove);
+criterion_main!(benches);
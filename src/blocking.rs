@@ -0,0 +1,49 @@
+use std::sync::Arc;
+
+use tokio::sync::oneshot;
+
+use crate::error::JobError;
+
+/// A unit of blocking work handed to a `BlockingExecutor`.
+pub type BlockingTask = Box<dyn FnOnce() + Send>;
+
+/// Runs blocking jobs off the async runtime, e.g. on a dedicated thread pool.
+/// Without one, blocking jobs run on tokio's `spawn_blocking` pool.
+pub trait BlockingExecutor: Send + Sync + 'static {
+    fn execute(&self, task: BlockingTask);
+}
+
+impl<F> BlockingExecutor for F
+where
+    F: Fn(BlockingTask) + Send + Sync + 'static,
+{
+    fn execute(&self, task: BlockingTask) {
+        self(task)
+    }
+}
+
+/// Runs `f` on `executor`, or with `spawn_blocking` if there is none, and
+/// waits for its result. A panic in `f` becomes the run's error.
+pub(crate) async fn run_blocking<F>(
+    executor: Option<Arc<dyn BlockingExecutor>>,
+    f: F,
+) -> Result<(), JobError>
+where
+    F: FnOnce() -> Result<(), JobError> + Send + 'static,
+{
+    let executor = match executor {
+        Some(executor) => executor,
+        None => {
+            return tokio::task::spawn_blocking(f)
+                .await
+                .unwrap_or_else(|e| Err(e.into()))
+        }
+    };
+
+    let (tx, rx) = oneshot::channel();
+    executor.execute(Box::new(move || {
+        let _ = tx.send(f());
+    }));
+    rx.await
+        .unwrap_or_else(|_| Err("blocking run was dropped by its executor".into()))
+}
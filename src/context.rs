@@ -0,0 +1,28 @@
+use std::sync::Arc;
+
+use chrono::{DateTime, Utc};
+
+use crate::job::JobId;
+
+/// Describes the run a job closure is called for.
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct JobContext {
+    pub job: JobId,
+    pub name: Option<Arc<str>>,
+    /// The fire time this run serves. Retries keep the time of the run they
+    /// retry.
+    pub scheduled: DateTime<Utc>,
+    /// When the run began: when the job was called or, for a blocking job,
+    /// when it began on its thread. Runs queued behind earlier runs of their
+    /// job begin once those are over.
+    pub started: DateTime<Utc>,
+    /// Whether a later fire time was already due when the run started, i.e.
+    /// the run catches up on a missed event.
+    pub catch_up: bool,
+    /// The attempt number, starting from 1 and growing with each retry.
+    pub attempt: u32,
+    /// Whether the run was requested with `JobScheduler::run_now` rather
+    /// than scheduled.
+    pub manual: bool,
+}
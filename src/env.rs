@@ -0,0 +1,24 @@
+use std::sync::{Arc, Mutex};
+
+#[cfg(feature = "tokio")]
+use crate::blocking::BlockingExecutor;
+use crate::clock::Clock;
+use crate::event::Observers;
+use crate::queue::RunQueue;
+#[cfg(feature = "tokio")]
+use crate::tracker::RunTracker;
+use crate::wakeup::Wakeup;
+
+/// The parts of a `JobScheduler` that job runs need, including runs that
+/// finish on a spawned task.
+#[derive(Clone)]
+pub(crate) struct RunEnv {
+    #[cfg(feature = "tokio")]
+    pub(crate) tracker: Arc<RunTracker>,
+    pub(crate) observers: Observers,
+    pub(crate) queue: Arc<Mutex<RunQueue>>,
+    pub(crate) wakeup: Arc<Wakeup>,
+    pub(crate) clock: Arc<dyn Clock>,
+    #[cfg(feature = "tokio")]
+    pub(crate) blocking: Option<Arc<dyn BlockingExecutor>>,
+}
@@ -0,0 +1,39 @@
+use std::error::Error;
+use std::fmt;
+use std::time::Duration;
+
+/// The error a fallible job run failed with.
+pub type JobError = Box<dyn Error + Send + Sync>;
+
+/// Return types accepted from job closures and futures: `()` for infallible
+/// jobs, `Result<(), E>` for fallible ones.
+pub trait IntoJobResult {
+    fn into_job_result(self) -> Result<(), JobError>;
+}
+
+impl IntoJobResult for () {
+    fn into_job_result(self) -> Result<(), JobError> {
+        Ok(())
+    }
+}
+
+impl<E> IntoJobResult for Result<(), E>
+where
+    E: Into<JobError>,
+{
+    fn into_job_result(self) -> Result<(), JobError> {
+        self.map_err(Into::into)
+    }
+}
+
+/// The error recorded for a run that was cancelled by its job's timeout.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimedOut(pub Duration);
+
+impl fmt::Display for TimedOut {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "job run timed out after {:?}", self.0)
+    }
+}
+
+impl Error for TimedOut {}
@@ -0,0 +1,73 @@
+use std::io;
+use std::sync::Arc;
+
+use chrono::{DateTime, Utc};
+
+use crate::error::JobError;
+use crate::job::JobId;
+use crate::policy::OverlapDecision;
+
+/// Something that happened to a job, reported to every `Observer`.
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub enum JobEvent {
+    /// A run became due while earlier runs of the job were in flight.
+    Overlap {
+        job: JobId,
+        decision: OverlapDecision,
+    },
+    /// An attempt of a run returned an error. Attempts are numbered from 1.
+    Failed {
+        job: JobId,
+        attempt: u32,
+        error: Arc<JobError>,
+    },
+    /// An attempt of a run was cancelled by the job's timeout. It is also
+    /// reported as `Failed` with a `TimedOut` error.
+    TimedOut { job: JobId, attempt: u32 },
+    /// A failed run will be attempted again at `at`.
+    RetryScheduled {
+        job: JobId,
+        attempt: u32,
+        at: DateTime<Utc>,
+    },
+    /// The job's trigger will not fire again and its last run is over, so it
+    /// was removed from the scheduler. `result` is the outcome of that last
+    /// run, if there was one.
+    Completed {
+        job: JobId,
+        result: Option<Result<(), Arc<JobError>>>,
+    },
+    /// The state store failed to load or save the job's last tick.
+    StateStore { job: JobId, error: Arc<io::Error> },
+}
+
+/// Receives `JobEvent`s from a `JobScheduler`. Observers may be called from
+/// spawned tasks and should not block.
+pub trait Observer: Send + Sync + 'static {
+    fn on_event(&self, event: &JobEvent);
+}
+
+impl<F> Observer for F
+where
+    F: Fn(&JobEvent) + Send + Sync + 'static,
+{
+    fn on_event(&self, event: &JobEvent) {
+        self(event)
+    }
+}
+
+#[derive(Clone, Default)]
+pub(crate) struct Observers(Arc<Vec<Arc<dyn Observer>>>);
+
+impl Observers {
+    pub(crate) fn push(&mut self, observer: Arc<dyn Observer>) {
+        Arc::make_mut(&mut self.0).push(observer);
+    }
+
+    pub(crate) fn emit(&self, event: JobEvent) {
+        for observer in self.0.iter() {
+            observer.on_event(&event);
+        }
+    }
+}
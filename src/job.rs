@@ -0,0 +1,692 @@
+use std::fmt;
+#[cfg(feature = "tokio")]
+use std::future::Future;
+#[cfg(feature = "tokio")]
+use std::pin::Pin;
+#[cfg(feature = "tokio")]
+use std::sync::PoisonError;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+#[cfg(feature = "tokio")]
+use tokio::sync::Semaphore;
+
+#[cfg(feature = "tokio")]
+use crate::blocking::run_blocking;
+use crate::clock::saturating_add;
+use crate::context::JobContext;
+use crate::env::RunEnv;
+#[cfg(feature = "tokio")]
+use crate::error::TimedOut;
+use crate::error::{IntoJobResult, JobError};
+use crate::event::JobEvent;
+#[cfg(feature = "tokio")]
+use crate::policy::OverlapDecision;
+use crate::policy::{MisfirePolicy, OverlapPolicy, ResumePolicy, StartPolicy};
+use crate::retry::RetryPolicy;
+use crate::trigger::Trigger;
+
+#[cfg(feature = "tokio")]
+type BoxFuture = Pin<Box<dyn Future<Output = Result<(), JobError>> + Send>>;
+#[cfg(feature = "tokio")]
+type AsyncFn = Mutex<Box<dyn FnMut(JobContext) -> BoxFuture + Send>>;
+type BlockingFn = dyn Fn(JobContext) -> Result<(), JobError> + Send + Sync;
+
+/// Identifies a job within the `JobScheduler` it was added to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct JobId(pub(crate) u64);
+
+impl fmt::Display for JobId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "job-{}", self.0)
+    }
+}
+
+enum Run {
+    Sync(Box<dyn FnMut(JobContext) -> Result<(), JobError> + Send>),
+    /// Shared with spawned runs, which call it once they begin.
+    #[cfg(feature = "tokio")]
+    Async(Arc<AsyncFn>),
+    Blocking(Arc<BlockingFn>),
+}
+
+#[cfg(feature = "tokio")]
+impl Run {
+    /// A handle on an async or blocking job for a spawned run.
+    fn share(&self) -> Run {
+        match self {
+            Run::Async(run) => Run::Async(run.clone()),
+            Run::Blocking(run) => Run::Blocking(run.clone()),
+            Run::Sync(_) => unreachable!("sync runs are not spawned"),
+        }
+    }
+
+    /// Begins a spawned run of an async or blocking job, which is called
+    /// only now, once any wait for earlier runs is over.
+    fn begin(&self, context: JobContext, env: &RunEnv) -> BoxFuture {
+        match self {
+            Run::Async(run) => {
+                let context = JobContext {
+                    started: env.clock.now(),
+                    ..context
+                };
+                let mut run = run.lock().unwrap_or_else(PoisonError::into_inner);
+                run(context)
+            }
+            Run::Blocking(run) => {
+                let run = run.clone();
+                let clock = env.clock.clone();
+                Box::pin(run_blocking(env.blocking.clone(), move || {
+                    run(JobContext {
+                        started: clock.now(),
+                        ..context
+                    })
+                }))
+            }
+            Run::Sync(_) => unreachable!("sync runs are not spawned"),
+        }
+    }
+}
+
+/// A scheduled unit of work. Sync and async jobs share this type so that a
+/// single `JobScheduler` can host any mix of them.
+pub struct Job {
+    name: Option<Arc<str>>,
+    trigger: Box<dyn Trigger>,
+    run: Run,
+    last_tick: Option<DateTime<Utc>>,
+    misfire: MisfirePolicy,
+    start: StartPolicy,
+    overlap: OverlapPolicy,
+    resume: ResumePolicy,
+    /// Periods whose events the resume policy skipped, kept until the last
+    /// tick has passed them.
+    skipped: Vec<(DateTime<Utc>, DateTime<Utc>)>,
+    #[cfg(feature = "tokio")]
+    queue: Arc<Semaphore>,
+    retry: Option<Arc<RetryPolicy>>,
+    timeout: Option<Duration>,
+    exhausted: bool,
+    state: Arc<Mutex<RunState>>,
+}
+
+/// What runs of a job record, possibly from a spawned task.
+#[derive(Default)]
+struct RunState {
+    failures: u64,
+    last_error: Option<Arc<JobError>>,
+    last_result: Option<Result<(), Arc<JobError>>>,
+    /// Runs waiting for their time: retries of failed runs and manual runs.
+    pending: Vec<Pending>,
+    /// When the job was paused, if it is. Its retries are only queued once
+    /// it is resumed.
+    paused: Option<DateTime<Utc>>,
+}
+
+struct Pending {
+    at: DateTime<Utc>,
+    context: JobContext,
+}
+
+impl Job {
+    /// Creates a sync job. The closure may return `()` or `Result<(), E>`.
+    pub fn new<T, F, R>(trigger: T, mut run: F) -> Job
+    where
+        T: Trigger,
+        F: FnMut() -> R + Send + 'static,
+        R: IntoJobResult,
+    {
+        Job::new_with_context(trigger, move |_| run())
+    }
+
+    /// Creates a sync job whose closure is told which run it serves.
+    pub fn new_with_context<T, F, R>(trigger: T, mut run: F) -> Job
+    where
+        T: Trigger,
+        F: FnMut(JobContext) -> R + Send + 'static,
+        R: IntoJobResult,
+    {
+        Job::with_run(
+            Box::new(trigger),
+            Run::Sync(Box::new(move |context| run(context).into_job_result())),
+        )
+    }
+
+    /// Creates an async job. The future may resolve to `()` or
+    /// `Result<(), E>`.
+    #[cfg(feature = "tokio")]
+    pub fn new_async<T, F, C>(trigger: T, mut run: F) -> Job
+    where
+        T: Trigger,
+        F: FnMut() -> C + Send + 'static,
+        C: Future + Send + 'static,
+        C::Output: IntoJobResult,
+    {
+        Job::new_async_with_context(trigger, move |_| run())
+    }
+
+    /// Creates an async job whose closure is told which run it serves.
+    #[cfg(feature = "tokio")]
+    pub fn new_async_with_context<T, F, C>(trigger: T, mut run: F) -> Job
+    where
+        T: Trigger,
+        F: FnMut(JobContext) -> C + Send + 'static,
+        C: Future + Send + 'static,
+        C::Output: IntoJobResult,
+    {
+        Job::with_run(
+            Box::new(trigger),
+            Run::Async(Arc::new(Mutex::new(Box::new(move |context| {
+                let fut = run(context);
+                Box::pin(async move { fut.await.into_job_result() })
+            })))),
+        )
+    }
+
+    /// Creates a job for CPU-heavy or blocking work. The run loop executes it
+    /// on a blocking thread pool, with the same overlap, timeout and retry
+    /// handling as async jobs; `tick` runs it inline. A timeout stops waiting
+    /// for the run but cannot interrupt it.
+    pub fn new_blocking<T, F, R>(trigger: T, run: F) -> Job
+    where
+        T: Trigger,
+        F: Fn() -> R + Send + Sync + 'static,
+        R: IntoJobResult,
+    {
+        Job::new_blocking_with_context(trigger, move |_| run())
+    }
+
+    /// Creates a blocking job whose closure is told which run it serves.
+    pub fn new_blocking_with_context<T, F, R>(trigger: T, run: F) -> Job
+    where
+        T: Trigger,
+        F: Fn(JobContext) -> R + Send + Sync + 'static,
+        R: IntoJobResult,
+    {
+        Job::with_run(
+            Box::new(trigger),
+            Run::Blocking(Arc::new(move |context| run(context).into_job_result())),
+        )
+    }
+
+    fn with_run(trigger: Box<dyn Trigger>, run: Run) -> Job {
+        Job {
+            name: None,
+            trigger,
+            run,
+            last_tick: None,
+            misfire: MisfirePolicy::default(),
+            start: StartPolicy::default(),
+            overlap: OverlapPolicy::default(),
+            resume: ResumePolicy::default(),
+            skipped: Vec::new(),
+            #[cfg(feature = "tokio")]
+            queue: Arc::new(Semaphore::new(1)),
+            retry: None,
+            timeout: None,
+            exhausted: false,
+            state: Arc::default(),
+        }
+    }
+
+    /// Names the job. Only named jobs have their state persisted.
+    pub fn with_name(mut self, name: impl Into<String>) -> Job {
+        self.name = Some(name.into().into());
+        self
+    }
+
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    /// Sets how many missed events are caught up on once the job is ticked.
+    pub fn with_misfire_policy(mut self, misfire: MisfirePolicy) -> Job {
+        self.misfire = misfire;
+        self
+    }
+
+    pub fn misfire_policy(&self) -> MisfirePolicy {
+        self.misfire
+    }
+
+    /// Sets what the job's first due events are counted from.
+    pub fn with_start_policy(mut self, start: StartPolicy) -> Job {
+        self.start = start;
+        self
+    }
+
+    pub fn start_policy(&self) -> StartPolicy {
+        self.start
+    }
+
+    pub(crate) fn last_tick(&self) -> Option<DateTime<Utc>> {
+        self.last_tick
+    }
+
+    /// Resumes from a last tick persisted by an earlier process.
+    pub(crate) fn restore(&mut self, last_tick: DateTime<Utc>) {
+        self.last_tick = Some(last_tick);
+    }
+
+    /// Applies the start policy when the job is added to a scheduler.
+    pub(crate) fn on_add(&mut self, now: DateTime<Utc>) {
+        self.trigger.start(now);
+        match self.start {
+            StartPolicy::Added => self.last_tick = Some(now),
+            StartPolicy::At(start) => self.last_tick = Some(start),
+            // Just before `now`, so that an event at `now` itself is due.
+            StartPolicy::FirstTick if self.trigger.counts_from_add() => {
+                self.last_tick = Some(now - chrono::Duration::nanoseconds(1));
+            }
+            StartPolicy::FirstTick | StartPolicy::Immediately => {}
+        }
+    }
+
+    /// Sets how runs of an async or blocking job that overlap earlier runs are handled.
+    pub fn with_overlap_policy(mut self, overlap: OverlapPolicy) -> Job {
+        self.overlap = overlap;
+        self
+    }
+
+    pub fn overlap_policy(&self) -> OverlapPolicy {
+        self.overlap
+    }
+
+    /// Sets what happens to events that fall due while the job is paused.
+    pub fn with_resume_policy(mut self, resume: ResumePolicy) -> Job {
+        self.resume = resume;
+        self
+    }
+
+    pub fn resume_policy(&self) -> ResumePolicy {
+        self.resume
+    }
+
+    /// Whether the job was paused with `JobScheduler::pause_job`.
+    pub fn is_paused(&self) -> bool {
+        self.state.lock().unwrap().paused.is_some()
+    }
+
+    pub(crate) fn pause(&mut self, now: DateTime<Utc>) {
+        self.state.lock().unwrap().paused = Some(now);
+    }
+
+    /// Lets the job run again, applying the resume policy to the events that
+    /// fell due while it was paused. Returns whether the last tick moved.
+    pub(crate) fn resume(&mut self, now: DateTime<Utc>) -> bool {
+        let paused = self.state.lock().unwrap().paused.take();
+        paused.is_some_and(|paused| self.on_resume(paused, now))
+    }
+
+    /// Applies the resume policy to the events that fell due after `paused`
+    /// up to `now`. Returns whether the last tick moved.
+    pub(crate) fn on_resume(&mut self, paused: DateTime<Utc>, now: DateTime<Utc>) -> bool {
+        let last_tick = match self.last_tick {
+            Some(last_tick) if self.resume == ResumePolicy::Skip => last_tick,
+            _ => return false,
+        };
+        // Events that were due before the pause are still run.
+        if self
+            .next_event_after(&last_tick)
+            .is_some_and(|next| next <= paused)
+        {
+            self.skipped.push((paused, now));
+            return false;
+        }
+        self.last_tick = Some(last_tick.max(now));
+        self.last_tick != Some(last_tick)
+    }
+
+    /// Retries failed runs according to `retry`.
+    pub fn with_retry_policy(mut self, retry: RetryPolicy) -> Job {
+        self.retry = Some(Arc::new(retry));
+        self
+    }
+
+    pub fn retry_policy(&self) -> Option<&RetryPolicy> {
+        self.retry.as_deref()
+    }
+
+    /// Cancels runs of an async or blocking job that take longer than `timeout`. Time
+    /// spent queued behind earlier runs does not count. Timed out runs are
+    /// failures and may be retried.
+    pub fn with_timeout(mut self, timeout: Duration) -> Job {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    pub fn timeout(&self) -> Option<Duration> {
+        self.timeout
+    }
+
+    pub fn schedule(&self) -> &dyn Trigger {
+        self.trigger.as_ref()
+    }
+
+    pub(crate) fn set_schedule(&mut self, trigger: Box<dyn Trigger>) -> Box<dyn Trigger> {
+        self.exhausted = false;
+        std::mem::replace(&mut self.trigger, trigger)
+    }
+
+    /// The first scheduled event strictly after `after`, leaving out those
+    /// the resume policy skipped.
+    pub(crate) fn next_event_after(&self, after: &DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let mut next = self.trigger.next_after(after)?;
+        while let Some(&(_, until)) = self
+            .skipped
+            .iter()
+            .find(|&&(from, until)| from < next && next <= until)
+        {
+            next = self.trigger.next_after(&until)?;
+        }
+        Some(next)
+    }
+
+    /// When the job next needs a tick: at its next event, retry or manual
+    /// run.
+    pub(crate) fn next_due(&self, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        self.next_event_due(now)
+            .into_iter()
+            .chain(self.next_pending())
+            .min()
+    }
+
+    /// When the job's schedule next needs a tick. Jobs that were never
+    /// ticked, or whose trigger ran out since their last tick, are due at
+    /// once.
+    fn next_event_due(&self, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        match self.last_tick {
+            Some(last_tick) => self
+                .next_event_after(&last_tick)
+                .or((!self.exhausted).then_some(now)),
+            None => Some(now),
+        }
+    }
+
+    /// The earliest pending retry or manual run.
+    fn next_pending(&self) -> Option<DateTime<Utc>> {
+        let state = self.state.lock().unwrap();
+        state.pending.iter().map(|pending| pending.at).min()
+    }
+
+    /// Adds a manual run, off the schedule, to be started on the next tick.
+    pub(crate) fn request_run(&mut self, id: JobId, now: DateTime<Utc>) {
+        let context = JobContext {
+            job: id,
+            name: self.name.clone(),
+            scheduled: now,
+            started: now,
+            catch_up: false,
+            attempt: 1,
+            manual: true,
+        };
+        let pending = Pending { at: now, context };
+        self.state.lock().unwrap().pending.push(pending);
+    }
+
+    /// How many runs of the job have failed, counting each attempt.
+    pub fn failures(&self) -> u64 {
+        self.state.lock().unwrap().failures
+    }
+
+    /// The error of the most recent failed run.
+    pub fn last_error(&self) -> Option<Arc<JobError>> {
+        self.state.lock().unwrap().last_error.clone()
+    }
+
+    /// The outcome of the most recent run, once one has finished.
+    pub fn last_result(&self) -> Option<Result<(), Arc<JobError>>> {
+        self.state.lock().unwrap().last_result.clone()
+    }
+
+    /// Whether the trigger will not fire again and nothing of the job is
+    /// still running or waiting to be retried or run manually.
+    pub(crate) fn is_finished(&self, id: JobId, env: &RunEnv) -> bool {
+        #[cfg(feature = "tokio")]
+        let idle = env.tracker.running_count(id) == 0;
+        // Without tokio every run is over by the time `tick` returns.
+        #[cfg(not(feature = "tokio"))]
+        let idle = {
+            let _ = (id, env);
+            true
+        };
+        self.exhausted && self.state.lock().unwrap().pending.is_empty() && idle
+    }
+
+    /// Async jobs are only driven by `JobScheduler::async_tick`.
+    #[cfg(feature = "tokio")]
+    pub fn is_async(&self) -> bool {
+        matches!(self.run, Run::Async(_))
+    }
+
+    /// Async jobs are only driven by `JobScheduler::async_tick`.
+    #[cfg(not(feature = "tokio"))]
+    pub fn is_async(&self) -> bool {
+        false
+    }
+
+    /// Returns the events to run at `now`, as picked by the misfire policy,
+    /// and advances `last_tick`. Each event comes with whether it is a
+    /// catch-up run.
+    fn due_events(&mut self, now: DateTime<Utc>) -> Vec<(DateTime<Utc>, bool)> {
+        self.exhausted = self.next_event_after(&now).is_none();
+        let events = match self.last_tick.replace(now) {
+            Some(last_tick) => {
+                let due = std::iter::successors(self.next_event_after(&last_tick), |event| {
+                    self.next_event_after(event)
+                })
+                .take_while(|event| *event <= now);
+                self.misfire
+                    .select(due)
+                    .into_iter()
+                    .map(|event| {
+                        let catch_up = self
+                            .next_event_after(&event)
+                            .is_some_and(|next| next <= now);
+                        (event, catch_up)
+                    })
+                    .collect()
+            }
+            None if self.start == StartPolicy::Immediately => vec![(now, false)],
+            None => Vec::new(),
+        };
+        // Skipped periods that the last tick has passed no longer matter.
+        self.skipped.retain(|&(_, until)| until > now);
+        events
+    }
+
+    /// Returns the runs due at `now`, including retries and manual runs,
+    /// whose start time is set again as each one begins. The flag tells
+    /// whether the last tick moved, which it only does when the schedule was
+    /// due.
+    fn due_runs(&mut self, id: JobId, now: DateTime<Utc>) -> (bool, Vec<JobContext>) {
+        let last_tick = self.last_tick;
+        let events = match self.next_event_due(now) {
+            Some(at) if at <= now => self.due_events(now),
+            _ => Vec::new(),
+        };
+        let moved = self.last_tick != last_tick;
+        let mut runs: Vec<JobContext> = events
+            .into_iter()
+            .map(|(scheduled, catch_up)| JobContext {
+                job: id,
+                name: self.name.clone(),
+                scheduled,
+                started: now,
+                catch_up,
+                attempt: 1,
+                manual: false,
+            })
+            .collect();
+
+        let mut state = self.state.lock().unwrap();
+        state.pending.retain(|pending| {
+            if pending.at <= now {
+                runs.push(JobContext {
+                    started: now,
+                    ..pending.context.clone()
+                });
+                false
+            } else {
+                true
+            }
+        });
+        (moved, runs)
+    }
+
+    /// Runs due sync runs inline and spawns due async and blocking runs as
+    /// tokio tasks.
+    /// Returns whether the last tick moved.
+    #[cfg(feature = "tokio")]
+    pub(crate) fn spawn_tick(&mut self, id: JobId, env: &RunEnv, now: DateTime<Utc>) -> bool {
+        // Once shut down, the job is left as it is: nothing would run.
+        if env.tracker.is_closed() {
+            return false;
+        }
+        let (moved, runs) = self.due_runs(id, now);
+        let mut started = false;
+        for context in runs {
+            if let Run::Sync(run) = &mut self.run {
+                let _guard = match env.tracker.begin(id) {
+                    Some(guard) => guard,
+                    None => break,
+                };
+                started = true;
+                let context = JobContext {
+                    started: env.clock.now(),
+                    ..context
+                };
+                let result = run(context.clone());
+                finish(&context, result, &self.state, self.retry.as_deref(), env);
+                continue;
+            }
+
+            let running = env.tracker.running_count(id);
+            if running > 0 {
+                let decision = self.overlap.decide(running);
+                env.observers.emit(JobEvent::Overlap { job: id, decision });
+                match decision {
+                    OverlapDecision::Skipped => continue,
+                    OverlapDecision::Replaced => env.tracker.abort_job(id),
+                    OverlapDecision::Queued | OverlapDecision::Parallel => {}
+                }
+            }
+
+            let run = self.run.share();
+            let queue = (self.overlap == OverlapPolicy::Queue).then(|| self.queue.clone());
+            let state = self.state.clone();
+            let retry = self.retry.clone();
+            let timeout = self.timeout;
+            // Any run may be the last of its job, which can then be
+            // removed, even if the trigger had not run out when it began.
+            let wake = env.wakeup.clone();
+            let task_env = env.clone();
+            let fut = async move {
+                let _permit = match &queue {
+                    Some(queue) => Some(queue.acquire().await),
+                    None => None,
+                };
+                let fut = run.begin(context.clone(), &task_env);
+                let result = match timeout {
+                    Some(timeout) => match tokio::time::timeout(timeout, fut).await {
+                        Ok(result) => result,
+                        Err(_) => {
+                            task_env.observers.emit(JobEvent::TimedOut {
+                                job: id,
+                                attempt: context.attempt,
+                            });
+                            Err(TimedOut(timeout).into())
+                        }
+                    },
+                    None => fut.await,
+                };
+                finish(&context, result, &state, retry.as_deref(), &task_env);
+            };
+            if !env.tracker.spawn(id, fut, wake) {
+                break;
+            }
+            started = true;
+        }
+        // A shutdown that refused every run leaves the stored state alone.
+        moved && (started || !env.tracker.is_closed())
+    }
+
+    /// Runs due sync and blocking runs inline. Returns whether the last tick
+    /// moved.
+    pub(crate) fn tick(&mut self, id: JobId, env: &RunEnv, now: DateTime<Utc>) -> bool {
+        if self.is_async() {
+            return false;
+        }
+
+        let (moved, runs) = self.due_runs(id, now);
+        for context in runs {
+            let context = JobContext {
+                started: env.clock.now(),
+                ..context
+            };
+            let result = match &mut self.run {
+                Run::Sync(run) => run(context.clone()),
+                Run::Blocking(run) => run(context.clone()),
+                #[cfg(feature = "tokio")]
+                Run::Async(_) => unreachable!(),
+            };
+            finish(&context, result, &self.state, self.retry.as_deref(), env);
+        }
+        moved
+    }
+}
+
+/// Records the outcome of a run and schedules a retry if it failed and the
+/// retry policy allows another attempt.
+fn finish(
+    context: &JobContext,
+    result: Result<(), JobError>,
+    state: &Mutex<RunState>,
+    retry: Option<&RetryPolicy>,
+    env: &RunEnv,
+) {
+    let error = match result {
+        Ok(()) => {
+            state.lock().unwrap().last_result = Some(Ok(()));
+            return;
+        }
+        Err(error) => Arc::new(error),
+    };
+    let delay = retry.and_then(|retry| retry.next_delay(context.attempt, &error));
+    {
+        let mut state = state.lock().unwrap();
+        state.failures += 1;
+        state.last_error = Some(error.clone());
+        state.last_result = Some(Err(error.clone()));
+    }
+    env.observers.emit(JobEvent::Failed {
+        job: context.job,
+        attempt: context.attempt,
+        error,
+    });
+
+    if let Some(delay) = delay {
+        let at = saturating_add(env.clock.now(), delay);
+        let context = JobContext {
+            attempt: context.attempt + 1,
+            ..context.clone()
+        };
+        env.observers.emit(JobEvent::RetryScheduled {
+            job: context.job,
+            attempt: context.attempt,
+            at,
+        });
+        let job = context.job;
+        let paused = {
+            let mut state = state.lock().unwrap();
+            state.pending.push(Pending { at, context });
+            state.paused.is_some()
+        };
+        // A paused job is queued again once it is resumed.
+        if !paused {
+            env.queue.lock().unwrap().schedule_by(job, at);
+            env.wakeup.wake();
+        }
+    }
+}
@@ -0,0 +1,129 @@
+use chrono::{DateTime, Utc};
+
+/// What to do when a job is due while earlier runs of it are still going.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OverlapPolicy {
+    /// Drop the new run.
+    Skip,
+    /// Start the new run once the earlier ones have finished.
+    Queue,
+    /// Run in parallel with the earlier runs as long as fewer than `n` are
+    /// in flight, otherwise drop the new run.
+    Allow(usize),
+    /// Cancel the running instances and start the new run.
+    Replace,
+}
+
+impl Default for OverlapPolicy {
+    fn default() -> Self {
+        OverlapPolicy::Allow(usize::MAX)
+    }
+}
+
+/// The decision an `OverlapPolicy` made for a run that overlapped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OverlapDecision {
+    Skipped,
+    Queued,
+    Parallel,
+    Replaced,
+}
+
+impl OverlapPolicy {
+    #[cfg(feature = "tokio")]
+    pub(crate) fn decide(self, running: usize) -> OverlapDecision {
+        match self {
+            OverlapPolicy::Skip => OverlapDecision::Skipped,
+            OverlapPolicy::Queue => OverlapDecision::Queued,
+            OverlapPolicy::Allow(n) if running < n.max(1) => OverlapDecision::Parallel,
+            OverlapPolicy::Allow(_) => OverlapDecision::Skipped,
+            OverlapPolicy::Replace => OverlapDecision::Replaced,
+        }
+    }
+}
+
+/// How many of the events that became due since a job's last tick are run.
+/// More than one is due when the scheduler fell behind, e.g. after a pause or
+/// a slow tick.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MisfirePolicy {
+    /// Run once for the oldest due event.
+    #[default]
+    FireOnce,
+    /// Run once for every due event.
+    FireAll,
+    /// Run once for each of the `n` oldest due events.
+    FireUpTo(usize),
+    /// Run only if a single event is due; if more were missed, skip them all.
+    SkipAll,
+    /// Run once for the newest due event.
+    FireLatest,
+}
+
+impl MisfirePolicy {
+    /// Picks the events to run out of the due ones, oldest first.
+    pub(crate) fn select<T>(self, mut due: impl Iterator<Item = T>) -> Vec<T> {
+        match self {
+            MisfirePolicy::FireOnce => due.take(1).collect(),
+            MisfirePolicy::FireAll => due.collect(),
+            MisfirePolicy::FireUpTo(n) => due.take(n).collect(),
+            MisfirePolicy::SkipAll => {
+                let first = due.next();
+                match due.next() {
+                    Some(_) => Vec::new(),
+                    None => first.into_iter().collect(),
+                }
+            }
+            MisfirePolicy::FireLatest => due.last().into_iter().collect(),
+        }
+    }
+}
+
+/// The reference point a job's first due events are counted from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum StartPolicy {
+    /// The job's first tick. Nothing runs on that tick, unless the trigger
+    /// counts from when the job is added, as `Once` does.
+    #[default]
+    FirstTick,
+    /// The time the job is added to a `JobScheduler`, by its clock. Events
+    /// due between then and the first tick are run.
+    Added,
+    /// An explicit instant. Events due after it are run on the first tick,
+    /// subject to the misfire policy.
+    At(DateTime<Utc>),
+    /// Run once on the first tick, then follow the schedule.
+    Immediately,
+}
+
+/// What happens to the events of a job that fell due while it or its
+/// scheduler was paused.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ResumePolicy {
+    /// Drop them; the job carries on with its next event after the resume.
+    /// Events that were already due when it was paused still run.
+    #[default]
+    Skip,
+    /// Run them on the next tick, as picked by the misfire policy.
+    CatchUp,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn misfire_policies_select_from_the_oldest_event() {
+        let select = |misfire: MisfirePolicy, due: usize| misfire.select(1..=due);
+        assert_eq!(select(MisfirePolicy::FireOnce, 4), [1]);
+        assert_eq!(select(MisfirePolicy::FireAll, 4), [1, 2, 3, 4]);
+        assert_eq!(select(MisfirePolicy::FireUpTo(2), 4), [1, 2]);
+        assert!(select(MisfirePolicy::FireUpTo(0), 4).is_empty());
+        assert!(select(MisfirePolicy::SkipAll, 4).is_empty());
+        assert_eq!(select(MisfirePolicy::SkipAll, 1), [1]);
+        assert_eq!(select(MisfirePolicy::FireLatest, 4), [4]);
+        for misfire in [MisfirePolicy::FireOnce, MisfirePolicy::FireLatest] {
+            assert!(select(misfire, 0).is_empty());
+        }
+    }
+}
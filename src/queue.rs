@@ -0,0 +1,276 @@
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+
+use crate::job::JobId;
+use crate::wheel::TimingWheel;
+
+/// Jobs ordered by when they next need a tick, so that a tick only touches
+/// the jobs that are due. Rescheduled and removed jobs leave their old
+/// entries behind; those are skipped once they surface.
+#[derive(Default)]
+pub(crate) struct RunQueue {
+    entries: Entries,
+    due: HashMap<JobId, DateTime<Utc>>,
+}
+
+enum Entries {
+    Heap(BinaryHeap<Reverse<(DateTime<Utc>, JobId)>>),
+    Wheel(TimingWheel),
+}
+
+impl Default for Entries {
+    fn default() -> Self {
+        Entries::Heap(BinaryHeap::new())
+    }
+}
+
+impl Entries {
+    fn push(&mut self, at: DateTime<Utc>, job: JobId) {
+        match self {
+            Entries::Heap(heap) => heap.push(Reverse((at, job))),
+            Entries::Wheel(wheel) => wheel.insert(at, job),
+        }
+    }
+
+    fn len(&self) -> usize {
+        match self {
+            Entries::Heap(heap) => heap.len(),
+            Entries::Wheel(wheel) => wheel.len(),
+        }
+    }
+}
+
+impl RunQueue {
+    /// Moves the queued jobs onto a timing wheel of the given resolution.
+    pub(crate) fn use_wheel(&mut self, resolution: Duration) {
+        self.entries = Entries::Wheel(TimingWheel::new(resolution));
+        for (&job, &at) in &self.due {
+            self.entries.push(at, job);
+        }
+    }
+
+    /// Sets when `job` is next due, or takes it off the queue.
+    pub(crate) fn schedule(&mut self, job: JobId, at: Option<DateTime<Utc>>) {
+        match at {
+            Some(at) => {
+                if self.due.insert(job, at) != Some(at) {
+                    self.entries.push(at, job);
+                }
+            }
+            None => {
+                self.due.remove(&job);
+            }
+        }
+        // Keep outdated entries from piling up when jobs are rescheduled far
+        // ahead of their old due time.
+        if self.entries.len() > 2 * self.due.len() + 64 {
+            let due = &self.due;
+            match &mut self.entries {
+                Entries::Heap(heap) => heap.retain(|Reverse((at, job))| due.get(job) == Some(at)),
+                Entries::Wheel(wheel) => wheel.retain(|(at, job)| due.get(job) == Some(at)),
+            }
+        }
+    }
+
+    /// Moves `job` forward to `at`, unless it is already due by then.
+    pub(crate) fn schedule_by(&mut self, job: JobId, at: DateTime<Utc>) {
+        if self.due.get(&job).is_none_or(|&due| at < due) {
+            self.schedule(job, Some(at));
+        }
+    }
+
+    /// The job due first, and when. On a timing wheel, that is when the
+    /// wheel reaches its slot.
+    pub(crate) fn peek(&mut self, now: DateTime<Utc>) -> Option<(JobId, DateTime<Utc>)> {
+        let due = &self.due;
+        match &mut self.entries {
+            Entries::Heap(heap) => {
+                while let Some(&Reverse((at, job))) = heap.peek() {
+                    if due.get(&job) == Some(&at) {
+                        return Some((job, at));
+                    }
+                    heap.pop();
+                }
+                None
+            }
+            Entries::Wheel(wheel) => wheel.peek(now, |(at, job)| due.get(job) == Some(at)),
+        }
+    }
+
+    /// Takes the jobs due at `now` off the queue, earliest first.
+    pub(crate) fn pop_due(&mut self, now: DateTime<Utc>) -> Vec<JobId> {
+        let entries = match &mut self.entries {
+            Entries::Heap(heap) => {
+                let mut entries = Vec::new();
+                while heap.peek().is_some_and(|Reverse((at, _))| *at <= now) {
+                    entries.push(heap.pop().unwrap().0);
+                }
+                entries
+            }
+            Entries::Wheel(wheel) => wheel.pop_due(now),
+        };
+        entries
+            .into_iter()
+            .filter(|(at, job)| {
+                // Only the first of duplicate entries still finds its job due.
+                let valid = self.due.get(job) == Some(at);
+                if valid {
+                    self.due.remove(job);
+                }
+                valid
+            })
+            .map(|(_, job)| job)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+    use crate::test_util::start;
+
+    struct Rng(u64);
+
+    impl Rng {
+        fn below(&mut self, n: u64) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0 % n.max(1)
+        }
+    }
+
+    /// `n` times `resolution`, which may be too long for a std `Duration`
+    /// multiplication.
+    fn ticks(resolution: Duration, n: i128) -> chrono::Duration {
+        let nanos = n * resolution.as_nanos() as i128;
+        chrono::Duration::seconds((nanos / 1_000_000_000) as i64)
+            + chrono::Duration::nanoseconds((nanos % 1_000_000_000) as i64)
+    }
+
+    /// Runs the same random operations on a heap and on a timing wheel and
+    /// checks that both give the same answers. Times are whole ticks of the
+    /// wheel, so that it does not round them.
+    fn compare(resolution: Duration, start: DateTime<Utc>, spread: Duration, seed: u64) {
+        let mut heap = RunQueue::default();
+        let mut wheel = RunQueue::default();
+        wheel.use_wheel(resolution);
+        let spread = (spread.as_nanos() / resolution.as_nanos()) as u64;
+        let mut rng = Rng(seed);
+        let mut now = start;
+        for _ in 0..20_000 {
+            let job = JobId(rng.below(300));
+            // Mostly ahead of `now`, some already overdue.
+            let offset = rng.below(spread) as i128 - (spread / 10) as i128;
+            let at = now + ticks(resolution, offset);
+            match rng.below(10) {
+                0..=3 => {
+                    heap.schedule(job, Some(at));
+                    wheel.schedule(job, Some(at));
+                }
+                4 => {
+                    heap.schedule(job, None);
+                    wheel.schedule(job, None);
+                }
+                5 => {
+                    heap.schedule_by(job, at);
+                    wheel.schedule_by(job, at);
+                }
+                6..=7 => {
+                    now += ticks(resolution, rng.below(spread / 20) as i128);
+                    assert_eq!(heap.pop_due(now), wheel.pop_due(now));
+                }
+                _ => assert_eq!(heap.peek(now), wheel.peek(now)),
+            }
+        }
+        now += ticks(resolution, 2 * spread as i128);
+        assert_eq!(heap.pop_due(now), wheel.pop_due(now));
+        assert_eq!(wheel.peek(now), None);
+    }
+
+    #[test]
+    fn wheel_matches_heap() {
+        for seed in 1..=3 {
+            compare(
+                Duration::from_millis(1),
+                start(),
+                Duration::from_secs(60),
+                seed,
+            );
+            compare(
+                Duration::from_secs(1),
+                start(),
+                Duration::from_secs(3 * 3600),
+                seed,
+            );
+        }
+    }
+
+    #[test]
+    fn wheel_matches_heap_past_top_level() {
+        // The top level reaches 64^6 ticks: about 69 s at 1 ns, and about
+        // 2.2 years at 1 ms.
+        for seed in 1..=3 {
+            compare(
+                Duration::from_nanos(1),
+                start(),
+                Duration::from_secs(600),
+                seed,
+            );
+            let years = Duration::from_secs(10 * 365 * 86_400);
+            compare(Duration::from_millis(1), start(), years, seed);
+        }
+    }
+
+    #[test]
+    fn wheel_matches_heap_before_epoch() {
+        let start = Utc.with_ymd_and_hms(1969, 12, 31, 23, 0, 0).unwrap();
+        for seed in 1..=3 {
+            compare(
+                Duration::from_millis(1),
+                start,
+                Duration::from_secs(3600),
+                seed,
+            );
+        }
+    }
+
+    #[test]
+    fn wheel_matches_heap_when_compacted() {
+        let mut heap = RunQueue::default();
+        let mut wheel = RunQueue::default();
+        wheel.use_wheel(Duration::from_millis(1));
+        let mut rng = Rng(7);
+        for _ in 0..1_000 {
+            let job = JobId(rng.below(10));
+            let at = start() + chrono::Duration::milliseconds(rng.below(100_000) as i64);
+            heap.schedule(job, Some(at));
+            wheel.schedule(job, Some(at));
+        }
+        assert!(wheel.entries.len() <= 2 * 10 + 64);
+        let now = start() + chrono::Duration::seconds(100);
+        assert_eq!(heap.peek(start()), wheel.peek(start()));
+        assert_eq!(heap.pop_due(now), wheel.pop_due(now));
+        assert_eq!(wheel.entries.len(), 0);
+    }
+
+    #[test]
+    fn wheel_rounds_up_to_its_resolution() {
+        let mut wheel = RunQueue::default();
+        wheel.use_wheel(Duration::from_secs(1));
+        let job = JobId(0);
+        wheel.schedule(job, Some(start() + chrono::Duration::milliseconds(1_500)));
+        let reached = start() + chrono::Duration::seconds(2);
+        assert_eq!(wheel.peek(start()), Some((job, reached)));
+        assert_eq!(
+            wheel.pop_due(reached - chrono::Duration::milliseconds(1)),
+            []
+        );
+        assert_eq!(wheel.pop_due(reached), [job]);
+    }
+}
@@ -0,0 +1,168 @@
+use std::collections::hash_map::RandomState;
+use std::fmt;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::error::JobError;
+
+type Retryable = dyn Fn(&JobError) -> bool + Send + Sync;
+
+/// How long to wait before retrying a failed run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backoff {
+    /// The same delay before every retry.
+    Fixed(Duration),
+    /// `initial` before the first retry, doubling for each further one up
+    /// to `max`.
+    Exponential { initial: Duration, max: Duration },
+}
+
+/// Retries failed runs of a job. Retries are scheduled by the scheduler like
+/// regular runs, so waiting for them never blocks other jobs.
+#[derive(Clone)]
+pub struct RetryPolicy {
+    max_attempts: u32,
+    backoff: Backoff,
+    jitter: f64,
+    retryable: Option<Arc<Retryable>>,
+}
+
+impl RetryPolicy {
+    /// Allows up to `max_attempts` attempts per run, counting the first one,
+    /// with a fixed backoff of one second.
+    pub fn new(max_attempts: u32) -> RetryPolicy {
+        RetryPolicy {
+            max_attempts,
+            backoff: Backoff::Fixed(Duration::from_secs(1)),
+            jitter: 0.0,
+            retryable: None,
+        }
+    }
+
+    pub fn with_backoff(mut self, backoff: Backoff) -> RetryPolicy {
+        self.backoff = backoff;
+        self
+    }
+
+    /// Shortens each delay by a random amount of up to `jitter` times the
+    /// delay. `jitter` is clamped to `0.0..=1.0`.
+    pub fn with_jitter(mut self, jitter: f64) -> RetryPolicy {
+        self.jitter = jitter.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Only retries errors for which `retryable` returns true. All errors are
+    /// retried by default.
+    pub fn retry_if<F>(mut self, retryable: F) -> RetryPolicy
+    where
+        F: Fn(&JobError) -> bool + Send + Sync + 'static,
+    {
+        self.retryable = Some(Arc::new(retryable));
+        self
+    }
+
+    pub fn max_attempts(&self) -> u32 {
+        self.max_attempts
+    }
+
+    pub fn backoff(&self) -> Backoff {
+        self.backoff
+    }
+
+    /// The delay before the next attempt, if attempt number `attempt` failed
+    /// with `error` and may be retried.
+    pub(crate) fn next_delay(&self, attempt: u32, error: &JobError) -> Option<Duration> {
+        if attempt >= self.max_attempts {
+            return None;
+        }
+        if let Some(retryable) = &self.retryable {
+            if !retryable(error) {
+                return None;
+            }
+        }
+
+        let delay = match self.backoff {
+            Backoff::Fixed(delay) => delay,
+            Backoff::Exponential { initial, max } => {
+                let factor = 2u32.saturating_pow(attempt.saturating_sub(1));
+                initial.saturating_mul(factor).min(max)
+            }
+        };
+        if self.jitter == 0.0 {
+            return Some(delay);
+        }
+        // Scaling in floating point can round past the longest `Duration`.
+        let jittered = delay.as_secs_f64() * (1.0 - self.jitter * random_unit());
+        Some(Duration::try_from_secs_f64(jittered).map_or(delay, |jittered| jittered.min(delay)))
+    }
+}
+
+impl fmt::Debug for RetryPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RetryPolicy")
+            .field("max_attempts", &self.max_attempts)
+            .field("backoff", &self.backoff)
+            .field("jitter", &self.jitter)
+            .field("retryable", &self.retryable.is_some())
+            .finish()
+    }
+}
+
+/// A random number in `0.0..1.0`, good enough for jitter.
+fn random_unit() -> f64 {
+    let bits = RandomState::new().build_hasher().finish();
+    (bits >> 11) as f64 / (1u64 << 53) as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error() -> JobError {
+        "failed".into()
+    }
+
+    #[test]
+    fn exponential_backoff_doubles_up_to_max() {
+        let retry = RetryPolicy::new(10).with_backoff(Backoff::Exponential {
+            initial: Duration::from_secs(1),
+            max: Duration::from_secs(5),
+        });
+        let delays: Vec<_> = (1..=5)
+            .map(|attempt| retry.next_delay(attempt, &error()))
+            .collect();
+        let secs = |secs| Some(Duration::from_secs(secs));
+        assert_eq!(delays, [secs(1), secs(2), secs(4), secs(5), secs(5)]);
+        assert_eq!(retry.next_delay(10, &error()), None);
+    }
+
+    #[test]
+    fn jitter_only_shortens_the_delay() {
+        let retry = RetryPolicy::new(3)
+            .with_backoff(Backoff::Fixed(Duration::from_secs(10)))
+            .with_jitter(0.5);
+        for _ in 0..100 {
+            let delay = retry.next_delay(1, &error()).unwrap();
+            assert!(delay > Duration::from_secs(5) && delay <= Duration::from_secs(10));
+        }
+    }
+
+    #[test]
+    fn longest_delays_do_not_overflow() {
+        let fixed = RetryPolicy::new(3).with_backoff(Backoff::Fixed(Duration::MAX));
+        assert_eq!(fixed.next_delay(1, &error()), Some(Duration::MAX));
+        let exponential = RetryPolicy::new(100)
+            .with_backoff(Backoff::Exponential {
+                initial: Duration::from_secs(u64::MAX / 2),
+                max: Duration::MAX,
+            })
+            .with_jitter(0.1);
+        for attempt in 1..50 {
+            let delay = exponential.next_delay(attempt, &error()).unwrap();
+            assert!(delay >= Duration::from_secs(u64::MAX / 4));
+        }
+        let jittered = fixed.with_jitter(1.0);
+        assert!(jittered.next_delay(1, &error()).is_some());
+    }
+}
@@ -0,0 +1,137 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::sync::{Mutex, MutexGuard};
+use tokio::task::JoinHandle;
+
+use crate::job::{Job, JobId};
+use crate::scheduler::JobScheduler;
+use crate::tracker::RunTracker;
+
+/// A cloneable handle to a scheduler driven by `JobScheduler::spawn`.
+#[derive(Clone)]
+pub struct SchedulerHandle {
+    scheduler: Arc<Mutex<JobScheduler>>,
+    shutdown: ShutdownHandle,
+}
+
+impl SchedulerHandle {
+    pub(crate) fn new(scheduler: JobScheduler) -> SchedulerHandle {
+        let shutdown = scheduler.shutdown_handle();
+        SchedulerHandle {
+            scheduler: Arc::new(Mutex::new(scheduler)),
+            shutdown,
+        }
+    }
+
+    pub async fn add(&self, job: Job) -> JobId {
+        self.scheduler.lock().await.add(job)
+    }
+
+    pub async fn remove(&self, id: JobId) -> Option<Job> {
+        self.scheduler.lock().await.remove(id)
+    }
+
+    /// Locks the scheduler. The run loop is blocked while the guard is held.
+    pub async fn lock(&self) -> MutexGuard<'_, JobScheduler> {
+        self.scheduler.lock().await
+    }
+
+    pub fn shutdown_handle(&self) -> ShutdownHandle {
+        self.shutdown.clone()
+    }
+
+    pub(crate) async fn run(self) {
+        let (wakeup, runs) = {
+            let scheduler = self.scheduler.lock().await;
+            (scheduler.env.wakeup.clone(), scheduler.env.tracker.clone())
+        };
+        while !runs.is_closed() {
+            let sleep = {
+                let mut scheduler = self.scheduler.lock().await;
+                scheduler.async_tick().await;
+                scheduler.next_job().map(|(_, _, wait)| wait)
+            };
+            tokio::select! {
+                _ = tokio::time::sleep(sleep.unwrap_or_default()), if sleep.is_some() => {}
+                _ = wakeup.woken() => {}
+                _ = runs.closed() => {}
+            }
+        }
+    }
+}
+
+/// Stops a running scheduler loop and waits for its in-flight job runs.
+#[derive(Clone)]
+pub struct ShutdownHandle {
+    runs: Arc<RunTracker>,
+    loop_task: Arc<std::sync::Mutex<Option<JoinHandle<()>>>>,
+}
+
+/// Outcome of `ShutdownHandle::shutdown`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ShutdownReport {
+    /// Jobs that were still running when the deadline expired. Their runs
+    /// have been cancelled.
+    pub still_running: Vec<JobId>,
+}
+
+impl ShutdownReport {
+    pub fn is_clean(&self) -> bool {
+        self.still_running.is_empty()
+    }
+}
+
+impl ShutdownHandle {
+    /// Stops firing new runs and waits up to `deadline` for the runs already
+    /// in flight to finish. Runs still going after the deadline are cancelled.
+    pub async fn shutdown(&self, deadline: Duration) -> ShutdownReport {
+        self.runs.close();
+        let mut loop_task = self.loop_task.lock().unwrap().take();
+
+        let finished = tokio::time::timeout(deadline, async {
+            if let Some(task) = &mut loop_task {
+                let _ = task.await;
+            }
+            self.runs.idle().await;
+        })
+        .await
+        .is_ok();
+
+        if finished {
+            return ShutdownReport {
+                still_running: Vec::new(),
+            };
+        }
+        let still_running = self.runs.running();
+        self.runs.abort_all();
+        if let Some(task) = loop_task {
+            task.abort();
+        }
+        ShutdownReport { still_running }
+    }
+}
+
+impl JobScheduler {
+    /// Drives the scheduler on the current task, sleeping until the next
+    /// due event between ticks. Returns once shut down.
+    pub async fn run(self) {
+        SchedulerHandle::new(self).run().await
+    }
+
+    /// Drives the scheduler on a new tokio task.
+    pub fn spawn(self) -> SchedulerHandle {
+        let handle = SchedulerHandle::new(self);
+        let task = tokio::spawn(handle.clone().run());
+        *handle.shutdown.loop_task.lock().unwrap() = Some(task);
+        handle
+    }
+
+    /// Returns a handle that stops `run` or `spawn` once triggered.
+    pub fn shutdown_handle(&self) -> ShutdownHandle {
+        ShutdownHandle {
+            runs: self.env.tracker.clone(),
+            loop_task: self.loop_task.clone(),
+        }
+    }
+}
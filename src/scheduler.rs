@@ -0,0 +1,1209 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::Arc;
+#[cfg(feature = "tokio")]
+use std::sync::Mutex;
+
+use chrono::{DateTime, Utc};
+
+#[cfg(feature = "tokio")]
+use tokio::task::JoinHandle;
+
+#[cfg(feature = "tokio")]
+use crate::blocking::BlockingExecutor;
+use crate::clock::{Clock, SystemClock};
+use crate::env::RunEnv;
+use crate::event::{JobEvent, Observer, Observers};
+use crate::job::{Job, JobId};
+use crate::store::StateStore;
+use crate::trigger::Trigger;
+
+pub struct JobScheduler {
+    pub(crate) jobs: BTreeMap<JobId, Job>,
+    next_id: u64,
+    /// Exhausted jobs that are only waiting for their last runs to end.
+    lingering: BTreeSet<JobId>,
+    /// Due async jobs that `tick` left to `async_tick`. They are kept off the
+    /// queue, where they would stay due in zero time for loops that only
+    /// call `tick`.
+    #[cfg(feature = "tokio")]
+    left_to_async: BTreeSet<JobId>,
+    /// When the scheduler was paused, if it is.
+    paused: Option<DateTime<Utc>>,
+    pub(crate) env: RunEnv,
+    #[cfg(feature = "tokio")]
+    pub(crate) loop_task: Arc<Mutex<Option<JoinHandle<()>>>>,
+    store: Option<Arc<dyn StateStore>>,
+}
+
+impl JobScheduler {
+    pub fn new() -> JobScheduler {
+        JobScheduler {
+            jobs: BTreeMap::new(),
+            next_id: 0,
+            lingering: BTreeSet::new(),
+            #[cfg(feature = "tokio")]
+            left_to_async: BTreeSet::new(),
+            paused: None,
+            env: RunEnv {
+                #[cfg(feature = "tokio")]
+                tracker: Arc::default(),
+                observers: Observers::default(),
+                queue: Arc::default(),
+                wakeup: Arc::default(),
+                clock: Arc::new(SystemClock),
+                #[cfg(feature = "tokio")]
+                blocking: None,
+            },
+            #[cfg(feature = "tokio")]
+            loop_task: Arc::default(),
+            store: None,
+        }
+    }
+
+    /// Replaces the system clock, which every time decision is based on.
+    pub fn with_clock(mut self, clock: impl Clock) -> JobScheduler {
+        self.env.clock = Arc::new(clock);
+        self
+    }
+
+    /// Runs blocking jobs on `executor` instead of tokio's blocking pool.
+    #[cfg(feature = "tokio")]
+    pub fn with_blocking_executor(mut self, executor: impl BlockingExecutor) -> JobScheduler {
+        self.env.blocking = Some(Arc::new(executor));
+        self
+    }
+
+    /// Queues jobs on a hierarchical timing wheel instead of a binary heap,
+    /// which keeps adding and firing jobs cheap at very large job counts.
+    /// Jobs run once the wheel reaches their slot, up to `resolution` late.
+    pub fn with_timing_wheel(self, resolution: std::time::Duration) -> JobScheduler {
+        self.env.queue.lock().unwrap().use_wheel(resolution);
+        self
+    }
+
+    /// Persists the last tick of named jobs in `store`, and restores it when a
+    /// job of the same name is added.
+    pub fn with_state_store(mut self, store: impl StateStore) -> JobScheduler {
+        self.store = Some(Arc::new(store));
+        self
+    }
+
+    pub fn add(&mut self, mut job: Job) -> JobId {
+        let id = JobId(self.next_id);
+        self.next_id += 1;
+
+        let now = self.env.clock.now();
+        job.on_add(now);
+        if let (Some(store), Some(name)) = (&self.store, job.name()) {
+            match store.load(name) {
+                Ok(Some(last_tick)) => job.restore(last_tick),
+                Ok(None) => {}
+                Err(e) => self.env.observers.emit(JobEvent::StateStore {
+                    job: id,
+                    error: Arc::new(e),
+                }),
+            }
+        }
+        self.env
+            .queue
+            .lock()
+            .unwrap()
+            .schedule(id, job.next_due(now));
+        self.jobs.insert(id, job);
+        self.env.wakeup.wake();
+        id
+    }
+
+    pub fn add_observer(&mut self, observer: impl Observer) {
+        self.env.observers.push(Arc::new(observer));
+    }
+
+    pub fn remove(&mut self, id: JobId) -> Option<Job> {
+        let job = self.jobs.remove(&id);
+        self.lingering.remove(&id);
+        #[cfg(feature = "tokio")]
+        self.left_to_async.remove(&id);
+        self.env.queue.lock().unwrap().schedule(id, None);
+        self.env.wakeup.wake();
+        job
+    }
+
+    pub fn get(&self, id: JobId) -> Option<&Job> {
+        self.jobs.get(&id)
+    }
+
+    pub fn contains(&self, id: JobId) -> bool {
+        self.jobs.contains_key(&id)
+    }
+
+    /// Swaps the schedule of a job, returning the old one. Missed runs are
+    /// evaluated against the new schedule from the job's last tick.
+    pub fn replace_schedule(
+        &mut self,
+        id: JobId,
+        schedule: impl Trigger,
+    ) -> Option<Box<dyn Trigger>> {
+        let job = self.jobs.get_mut(&id)?;
+        let old = job.set_schedule(Box::new(schedule));
+        // Paused jobs are queued again once resumed.
+        if !job.is_paused() {
+            let now = self.env.clock.now();
+            self.requeue(id, now);
+            self.env.wakeup.wake();
+        }
+        Some(old)
+    }
+
+    /// Runs a job on the next tick, off its schedule: its last tick and
+    /// upcoming events are left alone. Its overlap policy applies as to any
+    /// run. Returns false, queueing nothing, if the job is not found or is
+    /// paused, the scheduler is paused, or it has been shut down.
+    pub fn run_now(&mut self, id: JobId) -> bool {
+        #[cfg(feature = "tokio")]
+        if self.env.tracker.is_closed() {
+            return false;
+        }
+        if self.paused.is_some() {
+            return false;
+        }
+        let job = match self.jobs.get_mut(&id) {
+            Some(job) if !job.is_paused() => job,
+            _ => return false,
+        };
+        let now = self.env.clock.now();
+        job.request_run(id, now);
+        self.env.queue.lock().unwrap().schedule_by(id, now);
+        self.env.wakeup.wake();
+        true
+    }
+
+    /// Stops a job from running until `resume_job`, keeping its state. Runs
+    /// already in flight carry on. Returns whether a running job was paused.
+    pub fn pause_job(&mut self, id: JobId) -> bool {
+        let job = match self.jobs.get_mut(&id) {
+            Some(job) if !job.is_paused() => job,
+            _ => return false,
+        };
+        job.pause(self.env.clock.now());
+        self.env.queue.lock().unwrap().schedule(id, None);
+        #[cfg(feature = "tokio")]
+        self.left_to_async.remove(&id);
+        true
+    }
+
+    /// Lets a paused job run again, dealing with the events it missed as its
+    /// resume policy says. Returns whether a paused job was resumed.
+    pub fn resume_job(&mut self, id: JobId) -> bool {
+        let job = match self.jobs.get_mut(&id) {
+            Some(job) if job.is_paused() => job,
+            _ => return false,
+        };
+        let now = self.env.clock.now();
+        if job.resume(now) {
+            self.save_state(&[id]);
+        }
+        self.requeue(id, now);
+        self.env.wakeup.wake();
+        true
+    }
+
+    /// Stops all jobs from running until `resume`. Runs already in flight
+    /// carry on.
+    pub fn pause(&mut self) {
+        if self.paused.is_none() {
+            self.paused = Some(self.env.clock.now());
+        }
+    }
+
+    /// Lets jobs run again after `pause`, dealing with the events each missed
+    /// as its resume policy says. Jobs paused on their own stay paused.
+    pub fn resume(&mut self) {
+        let paused = match self.paused.take() {
+            Some(paused) => paused,
+            None => return,
+        };
+        let now = self.env.clock.now();
+        let ids: Vec<JobId> = self.jobs.keys().copied().collect();
+        let mut moved = Vec::new();
+        for id in ids {
+            let job = self.jobs.get_mut(&id).unwrap();
+            if !job.is_paused() {
+                if job.on_resume(paused, now) {
+                    moved.push(id);
+                }
+                self.requeue(id, now);
+            }
+        }
+        self.save_state(&moved);
+        self.env.wakeup.wake();
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused.is_some()
+    }
+
+    #[deprecated(note = "use `next_job`, which tells when nothing is scheduled")]
+    pub fn time_till_next_job(&self) -> std::time::Duration {
+        self.next_job()
+            .map_or(std::time::Duration::from_millis(500), |(_, _, wait)| wait)
+    }
+
+    /// The job with the nearest scheduled event or retry, when that is due
+    /// and how long until then. Overdue jobs are due in zero time. Nothing is
+    /// due while the scheduler is paused, nor are async jobs that `tick` left
+    /// to `async_tick`.
+    pub fn next_job(&self) -> Option<(JobId, DateTime<Utc>, std::time::Duration)> {
+        if self.paused.is_some() {
+            return None;
+        }
+        let now = self.env.clock.now();
+        let (id, next) = self.env.queue.lock().unwrap().peek(now)?;
+        Some((id, next, (next - now).to_std().unwrap_or_default()))
+    }
+
+    /// Runs due sync jobs and spawns due async and blocking jobs as
+    /// independent tokio tasks, so a slow job does not hold back the others.
+    #[cfg(feature = "tokio")]
+    pub async fn async_tick(&mut self) {
+        if self.paused.is_some() {
+            return;
+        }
+        let now = self.env.clock.now();
+        // Jobs left by `tick` have been due the longest.
+        let mut due: Vec<JobId> = std::mem::take(&mut self.left_to_async)
+            .into_iter()
+            .collect();
+        for id in self.env.queue.lock().unwrap().pop_due(now) {
+            if !due.contains(&id) {
+                due.push(id);
+            }
+        }
+        let mut moved = Vec::new();
+        for &id in &due {
+            if let Some(job) = self.jobs.get_mut(&id).filter(|job| !job.is_paused()) {
+                if job.spawn_tick(id, &self.env, now) {
+                    moved.push(id);
+                }
+            }
+        }
+        self.save_state(&moved);
+        self.settle(due, now);
+    }
+
+    /// Runs due sync and blocking jobs inline; async jobs are left to
+    /// `async_tick`, and the wait `next_job` gives leaves them out.
+    pub fn tick(&mut self) {
+        if self.paused.is_some() {
+            return;
+        }
+        let now = self.env.clock.now();
+        let due = self.env.queue.lock().unwrap().pop_due(now);
+        let mut moved = Vec::new();
+        let mut ticked = Vec::new();
+        for id in due {
+            let job = match self.jobs.get_mut(&id) {
+                Some(job) if !job.is_paused() => job,
+                _ => continue,
+            };
+            #[cfg(feature = "tokio")]
+            if job.is_async() {
+                self.left_to_async.insert(id);
+                // For a loop driving `async_tick` alongside.
+                self.env.wakeup.wake();
+                continue;
+            }
+            if job.tick(id, &self.env, now) {
+                moved.push(id);
+            }
+            ticked.push(id);
+        }
+        self.save_state(&moved);
+        self.settle(ticked, now);
+    }
+
+    /// Puts the jobs that were just ticked back on the queue, and removes
+    /// the jobs whose trigger will not fire again once their last run is
+    /// over.
+    fn settle(&mut self, ticked: Vec<JobId>, now: DateTime<Utc>) {
+        let mut queue = self.env.queue.lock().unwrap();
+        let mut check = std::mem::take(&mut self.lingering);
+        check.extend(ticked);
+        for id in check {
+            let job = match self.jobs.get(&id) {
+                Some(job) => job,
+                None => continue,
+            };
+            if job.is_finished(id, &self.env) {
+                let result = job.last_result();
+                self.jobs.remove(&id);
+                self.env
+                    .observers
+                    .emit(JobEvent::Completed { job: id, result });
+                continue;
+            }
+            // Paused jobs are queued again once resumed.
+            if job.is_paused() {
+                continue;
+            }
+            match job.next_due(now) {
+                Some(at) => queue.schedule_by(id, at),
+                None => {
+                    self.lingering.insert(id);
+                }
+            }
+        }
+    }
+
+    /// Queues a job anew after its due time may have moved.
+    fn requeue(&mut self, id: JobId, now: DateTime<Utc>) {
+        let next = self.jobs[&id].next_due(now);
+        self.env.queue.lock().unwrap().schedule(id, next);
+        if next.is_none() {
+            self.lingering.insert(id);
+        }
+    }
+
+    fn save_state(&self, ids: &[JobId]) {
+        let store = match &self.store {
+            Some(store) => store,
+            None => return,
+        };
+        let (saved, ticks): (Vec<JobId>, Vec<_>) = ids
+            .iter()
+            .filter_map(|id| {
+                let job = &self.jobs[id];
+                Some((*id, (job.name()?, job.last_tick()?)))
+            })
+            .unzip();
+        if ticks.is_empty() {
+            return;
+        }
+        if let Err(e) = store.save_all(&ticks) {
+            let error = Arc::new(e);
+            for job in saved {
+                self.env.observers.emit(JobEvent::StateStore {
+                    job,
+                    error: error.clone(),
+                });
+            }
+        }
+    }
+}
+
+impl Default for JobScheduler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::io;
+    use std::sync::Mutex;
+
+    use chrono::{DateTime, Utc};
+
+    use super::*;
+    use crate::clock::ManualClock;
+    use crate::context::JobContext;
+    #[cfg(feature = "tokio")]
+    use crate::error::TimedOut;
+    #[cfg(feature = "tokio")]
+    use crate::policy::OverlapPolicy;
+    use crate::policy::{MisfirePolicy, ResumePolicy, StartPolicy};
+    #[cfg(feature = "tokio")]
+    use crate::retry::RetryPolicy;
+    use crate::test_util::{at, start};
+    use crate::trigger::{Interval, Once};
+
+    fn scheduler() -> (JobScheduler, ManualClock) {
+        let clock = ManualClock::new(start());
+        (JobScheduler::new().with_clock(clock.clone()), clock)
+    }
+
+    #[derive(Clone, Default)]
+    struct Runs(Arc<Mutex<Vec<JobContext>>>);
+
+    impl Runs {
+        /// A job on `trigger` that records the runs it is called for.
+        fn job(&self, trigger: impl Trigger) -> Job {
+            let runs = self.0.clone();
+            Job::new_with_context(trigger, move |context| runs.lock().unwrap().push(context))
+        }
+
+        /// An async job on `trigger` that records the runs it is called for.
+        #[cfg(feature = "tokio")]
+        fn async_job(&self, trigger: impl Trigger) -> Job {
+            let runs = self.0.clone();
+            Job::new_async_with_context(trigger, move |context| {
+                runs.lock().unwrap().push(context);
+                async {}
+            })
+        }
+
+        fn every_minute(&self) -> Job {
+            self.job(Interval::new(std::time::Duration::from_secs(60)))
+        }
+
+        /// The runs recorded since the last call.
+        fn take(&self) -> Vec<JobContext> {
+            std::mem::take(&mut *self.0.lock().unwrap())
+        }
+
+        /// The fire times of the runs recorded since the last call.
+        fn scheduled(&self) -> Vec<DateTime<Utc>> {
+            self.take().into_iter().map(|run| run.scheduled).collect()
+        }
+    }
+
+    #[derive(Clone, Default)]
+    struct MemoryStore(Arc<Mutex<HashMap<String, DateTime<Utc>>>>);
+
+    impl StateStore for MemoryStore {
+        fn load(&self, job: &str) -> io::Result<Option<DateTime<Utc>>> {
+            Ok(self.0.lock().unwrap().get(job).copied())
+        }
+
+        fn save(&self, job: &str, last_tick: DateTime<Utc>) -> io::Result<()> {
+            self.0.lock().unwrap().insert(job.to_owned(), last_tick);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn misfire_policies_pick_the_missed_events() {
+        let cases = [
+            (MisfirePolicy::FireOnce, vec![at(60)]),
+            (
+                MisfirePolicy::FireAll,
+                vec![at(60), at(120), at(180), at(240)],
+            ),
+            (MisfirePolicy::FireUpTo(2), vec![at(60), at(120)]),
+            (MisfirePolicy::SkipAll, vec![]),
+            (MisfirePolicy::FireLatest, vec![at(240)]),
+        ];
+        for (misfire, expected) in cases {
+            let (mut scheduler, clock) = scheduler();
+            let runs = Runs::default();
+            scheduler.add(runs.every_minute().with_misfire_policy(misfire));
+            scheduler.tick();
+
+            clock.set(at(250));
+            scheduler.tick();
+            assert_eq!(runs.scheduled(), expected, "{misfire:?}");
+
+            // A single due event runs under every policy.
+            clock.set(at(300));
+            scheduler.tick();
+            assert_eq!(runs.scheduled(), [at(300)], "{misfire:?}");
+        }
+    }
+
+    #[test]
+    fn only_the_last_missed_event_is_not_a_catch_up() {
+        let (mut scheduler, clock) = scheduler();
+        let runs = Runs::default();
+        scheduler.add(
+            runs.every_minute()
+                .with_misfire_policy(MisfirePolicy::FireAll),
+        );
+        scheduler.tick();
+
+        clock.set(at(150));
+        scheduler.tick();
+        let catch_up: Vec<bool> = runs.take().iter().map(|run| run.catch_up).collect();
+        assert_eq!(catch_up, [true, false]);
+    }
+
+    #[test]
+    fn start_policies_set_where_events_are_counted_from() {
+        let cases = [
+            (StartPolicy::FirstTick, vec![]),
+            (StartPolicy::Added, vec![at(60)]),
+            (StartPolicy::At(at(-120)), vec![at(-60), at(0), at(60)]),
+            (StartPolicy::Immediately, vec![at(90)]),
+        ];
+        for (start, expected) in cases {
+            let (mut scheduler, clock) = scheduler();
+            let runs = Runs::default();
+            scheduler.add(
+                runs.every_minute()
+                    .with_start_policy(start)
+                    .with_misfire_policy(MisfirePolicy::FireAll),
+            );
+
+            // The first tick comes well after the job was added.
+            clock.set(at(90));
+            scheduler.tick();
+            assert_eq!(runs.scheduled(), expected, "{start:?}");
+
+            clock.set(at(120));
+            scheduler.tick();
+            assert_eq!(runs.scheduled(), [at(120)], "{start:?}");
+        }
+    }
+
+    #[test]
+    fn interval_anchored_ahead_fires_from_the_first_tick() {
+        let (mut scheduler, clock) = scheduler();
+        let runs = Runs::default();
+        let every_minute = Interval::new(std::time::Duration::from_secs(60));
+        scheduler.add(runs.job(every_minute.with_anchor(at(3 * 3600 + 15))));
+        scheduler.tick();
+
+        for secs in [15, 74, 75] {
+            clock.set(at(secs));
+            scheduler.tick();
+        }
+        assert_eq!(runs.scheduled(), [at(15), at(75)]);
+    }
+
+    #[test]
+    fn once_jobs_are_removed_after_their_run() {
+        let (mut scheduler, clock) = scheduler();
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let observed = events.clone();
+        scheduler
+            .add_observer(move |event: &JobEvent| observed.lock().unwrap().push(event.clone()));
+        let runs = Runs::default();
+        let id = scheduler.add(runs.job(Once::after(std::time::Duration::from_secs(10))));
+        scheduler.tick();
+        assert!(scheduler.contains(id));
+
+        clock.set(at(10));
+        scheduler.tick();
+        assert_eq!(runs.scheduled(), [at(10)]);
+        assert!(!scheduler.contains(id));
+        assert!(matches!(
+            events.lock().unwrap()[..],
+            [JobEvent::Completed {
+                job,
+                result: Some(Ok(())),
+            }] if job == id
+        ));
+
+        clock.set(at(3600));
+        scheduler.tick();
+        assert!(runs.take().is_empty());
+        assert_eq!(events.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn once_jobs_run_when_the_first_tick_is_late() {
+        let (mut scheduler, clock) = scheduler();
+        let runs = Runs::default();
+        let id = scheduler.add(runs.job(Once::after(std::time::Duration::from_millis(5))));
+        clock.set(start() + chrono::Duration::milliseconds(20));
+        scheduler.tick();
+        assert_eq!(
+            runs.scheduled(),
+            [start() + chrono::Duration::milliseconds(5)]
+        );
+        assert!(!scheduler.contains(id));
+    }
+
+    #[test]
+    fn once_jobs_without_delay_run_on_the_first_tick() {
+        let (mut scheduler, clock) = scheduler();
+        let runs = Runs::default();
+        scheduler.add(runs.job(Once::after(std::time::Duration::ZERO)));
+        scheduler.add(runs.job(Once::at(start())));
+        clock.set(at(1));
+        scheduler.tick();
+        assert_eq!(runs.scheduled(), [start(), start()]);
+        assert_eq!(scheduler.next_job(), None);
+    }
+
+    #[test]
+    fn once_jobs_added_after_their_instant_never_run() {
+        let (mut scheduler, _clock) = scheduler();
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let observed = events.clone();
+        scheduler
+            .add_observer(move |event: &JobEvent| observed.lock().unwrap().push(event.clone()));
+        let runs = Runs::default();
+        let id = scheduler.add(runs.job(Once::at(at(-1))));
+        scheduler.tick();
+        assert!(runs.take().is_empty());
+        assert!(!scheduler.contains(id));
+        assert!(matches!(
+            events.lock().unwrap()[..],
+            [JobEvent::Completed { job, result: None }] if job == id
+        ));
+    }
+
+    #[test]
+    fn paused_jobs_skip_or_catch_up_on_resume() {
+        for (resume, expected) in [
+            (ResumePolicy::Skip, vec![]),
+            (ResumePolicy::CatchUp, vec![at(60), at(120)]),
+        ] {
+            let (mut scheduler, clock) = scheduler();
+            let runs = Runs::default();
+            let id = scheduler.add(
+                runs.every_minute()
+                    .with_resume_policy(resume)
+                    .with_misfire_policy(MisfirePolicy::FireAll),
+            );
+            scheduler.tick();
+
+            assert!(scheduler.pause_job(id));
+            clock.set(at(150));
+            scheduler.tick();
+            assert!(runs.take().is_empty(), "{resume:?}");
+
+            assert!(scheduler.resume_job(id));
+            scheduler.tick();
+            assert_eq!(runs.scheduled(), expected, "{resume:?}");
+
+            clock.set(at(180));
+            scheduler.tick();
+            assert_eq!(runs.scheduled(), [at(180)], "{resume:?}");
+        }
+    }
+
+    #[test]
+    fn paused_schedulers_skip_or_catch_up_on_resume() {
+        let (mut scheduler, clock) = scheduler();
+        let skipped = Runs::default();
+        let caught_up = Runs::default();
+        scheduler.add(skipped.every_minute());
+        scheduler.add(
+            caught_up
+                .every_minute()
+                .with_resume_policy(ResumePolicy::CatchUp)
+                .with_misfire_policy(MisfirePolicy::FireAll),
+        );
+        scheduler.tick();
+
+        scheduler.pause();
+        assert!(scheduler.is_paused());
+        assert_eq!(scheduler.next_job(), None);
+        clock.set(at(150));
+        scheduler.tick();
+        assert!(skipped.take().is_empty());
+        assert!(caught_up.take().is_empty());
+
+        scheduler.resume();
+        scheduler.tick();
+        assert!(skipped.take().is_empty());
+        assert_eq!(caught_up.scheduled(), [at(60), at(120)]);
+
+        clock.set(at(180));
+        scheduler.tick();
+        assert_eq!(skipped.scheduled(), [at(180)]);
+        assert_eq!(caught_up.scheduled(), [at(180)]);
+    }
+
+    #[test]
+    fn skipping_on_resume_keeps_events_due_before_the_pause() {
+        let (mut scheduler, clock) = scheduler();
+        let runs = Runs::default();
+        let job = scheduler.add(
+            runs.every_minute()
+                .with_misfire_policy(MisfirePolicy::FireAll),
+        );
+        scheduler.tick();
+
+        clock.set(at(90));
+        assert!(scheduler.pause_job(job));
+        clock.set(at(150));
+        assert!(scheduler.resume_job(job));
+        assert_eq!(scheduler.upcoming(1), [(job, at(180))]);
+        scheduler.tick();
+        assert_eq!(runs.scheduled(), [at(60)]);
+
+        clock.set(at(200));
+        scheduler.pause();
+        clock.set(at(400));
+        scheduler.resume();
+        scheduler.tick();
+        assert_eq!(runs.scheduled(), [at(180)]);
+
+        clock.set(at(420));
+        scheduler.tick();
+        assert_eq!(runs.scheduled(), [at(420)]);
+    }
+
+    #[test]
+    fn paused_jobs_stay_off_the_queue_when_their_schedule_is_replaced() {
+        let (mut scheduler, _clock) = scheduler();
+        let runs = Runs::default();
+        let id = scheduler.add(runs.every_minute());
+        assert!(scheduler.pause_job(id));
+        scheduler.replace_schedule(id, Interval::new(std::time::Duration::from_secs(30)));
+        assert_eq!(scheduler.next_job(), None);
+
+        assert!(scheduler.resume_job(id));
+        assert_eq!(scheduler.next_job().map(|(job, _, _)| job), Some(id));
+    }
+
+    #[test]
+    fn failed_runs_are_counted_and_their_error_kept() {
+        let (mut scheduler, clock) = scheduler();
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let observed = events.clone();
+        scheduler
+            .add_observer(move |event: &JobEvent| observed.lock().unwrap().push(event.clone()));
+        let mut outcomes = vec![Err("second"), Ok(()), Err("first")];
+        let id = scheduler.add(
+            Job::new(
+                Interval::new(std::time::Duration::from_secs(60)),
+                move || outcomes.pop().unwrap(),
+            )
+            .with_start_policy(StartPolicy::Added),
+        );
+        let job = |scheduler: &JobScheduler| {
+            let job = scheduler.get(id).unwrap();
+            let error = job.last_error().map(|error| error.to_string());
+            let result = job.last_result().map(|result| result.is_ok());
+            (job.failures(), error, result)
+        };
+        assert_eq!(job(&scheduler), (0, None, None));
+
+        clock.set(at(60));
+        scheduler.tick();
+        assert_eq!(job(&scheduler), (1, Some("first".into()), Some(false)));
+
+        clock.set(at(120));
+        scheduler.tick();
+        assert_eq!(job(&scheduler), (1, Some("first".into()), Some(true)));
+
+        clock.set(at(180));
+        scheduler.tick();
+        assert_eq!(job(&scheduler), (2, Some("second".into()), Some(false)));
+
+        let failed: Vec<_> = events
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|event| match event {
+                JobEvent::Failed {
+                    job,
+                    attempt,
+                    error,
+                } => (*job, *attempt, error.to_string()),
+                event => panic!("unexpected event {event:?}"),
+            })
+            .collect();
+        assert_eq!(
+            failed,
+            [(id, 1, "first".to_string()), (id, 1, "second".to_string())]
+        );
+    }
+
+    #[test]
+    fn run_now_leaves_the_schedule_alone() {
+        let (mut scheduler, clock) = scheduler();
+        let runs = Runs::default();
+        let id = scheduler.add(runs.every_minute());
+        scheduler.tick();
+
+        clock.set(at(30));
+        assert!(scheduler.run_now(id));
+        scheduler.tick();
+        let manual = runs.take();
+        assert_eq!(manual.len(), 1);
+        assert!(manual[0].manual);
+        assert_eq!(manual[0].scheduled, at(30));
+        assert_eq!(scheduler.get(id).unwrap().last_tick(), Some(start()));
+        assert_eq!(
+            scheduler.next_job().map(|(job, next, _)| (job, next)),
+            Some((id, at(60)))
+        );
+
+        clock.set(at(60));
+        scheduler.tick();
+        let scheduled = runs.take();
+        assert_eq!(scheduled.len(), 1);
+        assert!(!scheduled[0].manual);
+        assert_eq!(scheduled[0].scheduled, at(60));
+    }
+
+    #[test]
+    fn last_tick_is_saved_before_the_first_run() {
+        let store = MemoryStore::default();
+        let (scheduler, clock) = scheduler();
+        let runs = Runs::default();
+        let job = || {
+            runs.every_minute()
+                .with_name("report")
+                .with_misfire_policy(MisfirePolicy::FireAll)
+        };
+
+        let mut scheduler = scheduler.with_state_store(store.clone());
+        scheduler.add(job());
+        scheduler.tick();
+        assert_eq!(store.load("report").unwrap(), Some(start()));
+
+        // Restart after two events were missed.
+        clock.set(at(150));
+        let mut scheduler = JobScheduler::new()
+            .with_clock(clock.clone())
+            .with_state_store(store.clone());
+        scheduler.add(job());
+        scheduler.tick();
+        assert_eq!(runs.scheduled(), [at(60), at(120)]);
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn ticks_after_a_shutdown_leave_the_stored_state_alone() {
+        let store = MemoryStore::default();
+        let (scheduler, clock) = scheduler();
+        let mut scheduler = scheduler.with_state_store(store.clone());
+        let runs = Runs::default();
+        let id = scheduler.add(runs.every_minute().with_name("report"));
+        scheduler.async_tick().await;
+        assert!(scheduler.run_now(id));
+
+        scheduler
+            .shutdown_handle()
+            .shutdown(std::time::Duration::ZERO)
+            .await;
+        clock.set(at(60));
+        scheduler.async_tick().await;
+        assert!(runs.take().is_empty());
+        assert_eq!(store.load("report").unwrap(), Some(start()));
+        assert_eq!(scheduler.jobs[&id].last_tick(), Some(start()));
+        // The manual run is still pending.
+        assert_eq!(scheduler.jobs[&id].next_due(at(60)), Some(start()));
+    }
+
+    #[test]
+    fn run_now_is_refused_while_paused() {
+        let (mut scheduler, _clock) = scheduler();
+        let runs = Runs::default();
+        let id = scheduler.add(runs.every_minute());
+
+        scheduler.pause();
+        assert!(!scheduler.run_now(id));
+        scheduler.resume();
+        scheduler.tick();
+        assert!(runs.take().is_empty());
+
+        scheduler.pause_job(id);
+        assert!(!scheduler.run_now(id));
+        scheduler.resume_job(id);
+        assert!(scheduler.run_now(id));
+        scheduler.tick();
+        assert_eq!(runs.take().len(), 1);
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn tick_leaves_due_async_jobs_to_async_tick() {
+        let (mut scheduler, clock) = scheduler();
+        let runs = Runs::default();
+        let once = scheduler.add(runs.async_job(Once::after(std::time::Duration::from_secs(10))));
+        let interval =
+            scheduler.add(runs.async_job(Interval::new(std::time::Duration::from_secs(60))));
+        scheduler.async_tick().await;
+
+        clock.set(at(60));
+        scheduler.tick();
+        // Not due in zero time, which would keep a `tick` loop spinning.
+        assert_eq!(scheduler.next_job(), None);
+        scheduler.async_tick().await;
+        scheduler.env.tracker.idle().await;
+        scheduler.async_tick().await;
+
+        assert_eq!(runs.scheduled(), [at(10), at(60)]);
+        assert!(!scheduler.contains(once));
+        assert!(scheduler.contains(interval));
+        let (next, next_at, _) = scheduler.next_job().unwrap();
+        assert_eq!((next, next_at), (interval, at(120)));
+    }
+
+    /// Receives the id of each job that completes on `scheduler`.
+    #[cfg(feature = "tokio")]
+    fn completions(scheduler: &mut JobScheduler) -> tokio::sync::mpsc::UnboundedReceiver<JobId> {
+        let (sender, receiver) = tokio::sync::mpsc::unbounded_channel();
+        scheduler.add_observer(move |event: &JobEvent| {
+            if let JobEvent::Completed { job, .. } = event {
+                let _ = sender.send(*job);
+            }
+        });
+        receiver
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn async_once_jobs_complete_after_an_immediate_run() {
+        let (mut scheduler, _clock) = scheduler();
+        let mut completed = completions(&mut scheduler);
+        let id = scheduler.add(
+            Job::new_async(Once::after(std::time::Duration::ZERO), || {
+                tokio::time::sleep(std::time::Duration::from_millis(20))
+            })
+            .with_start_policy(StartPolicy::Immediately),
+        );
+        let handle = scheduler.spawn();
+
+        let wait = std::time::Duration::from_secs(1);
+        assert_eq!(
+            tokio::time::timeout(wait, completed.recv()).await,
+            Ok(Some(id))
+        );
+        assert!(!handle.lock().await.contains(id));
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn async_once_jobs_complete_after_a_manual_run_that_outlives_the_last_event() {
+        let (mut scheduler, clock) = scheduler();
+        let mut completed = completions(&mut scheduler);
+        let (started, mut manual_started) = tokio::sync::mpsc::unbounded_channel();
+        let release = Arc::new(tokio::sync::Notify::new());
+        let released = release.clone();
+        let id = scheduler.add(Job::new_async_with_context(
+            Once::after(std::time::Duration::from_secs(10)),
+            move |context| {
+                let started = started.clone();
+                let released = released.clone();
+                async move {
+                    if context.manual {
+                        let _ = started.send(());
+                        released.notified().await;
+                    }
+                }
+            },
+        ));
+        assert!(scheduler.run_now(id));
+        let handle = scheduler.spawn();
+        manual_started.recv().await;
+
+        // The scheduled run ends first, leaving the job to wait for the
+        // manual one.
+        clock.set(at(10));
+        handle.lock().await.env.wakeup.wake();
+        loop {
+            let scheduler = handle.lock().await;
+            if scheduler.lingering.contains(&id) && scheduler.env.tracker.running_count(id) == 1 {
+                break;
+            }
+            drop(scheduler);
+            tokio::task::yield_now().await;
+        }
+
+        release.notify_one();
+        let wait = std::time::Duration::from_secs(1);
+        assert_eq!(
+            tokio::time::timeout(wait, completed.recv()).await,
+            Ok(Some(id))
+        );
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn retries_of_paused_jobs_wait_for_the_resume() {
+        let (mut scheduler, clock) = scheduler();
+        let release = Arc::new(tokio::sync::Notify::new());
+        let released = release.clone();
+        let id = scheduler.add(
+            Job::new_async(Once::after(std::time::Duration::ZERO), move || {
+                let released = released.clone();
+                async move {
+                    released.notified().await;
+                    Err::<(), _>("failed")
+                }
+            })
+            .with_retry_policy(RetryPolicy::new(2)),
+        );
+        scheduler.async_tick().await;
+
+        assert!(scheduler.pause_job(id));
+        release.notify_one();
+        scheduler.env.tracker.idle().await;
+        assert_eq!(scheduler.next_job(), None);
+
+        clock.set(at(5));
+        assert!(scheduler.resume_job(id));
+        assert_eq!(
+            scheduler.next_job(),
+            Some((id, at(1), std::time::Duration::ZERO))
+        );
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn queued_runs_start_once_the_earlier_run_is_over() {
+        let (mut scheduler, clock) = scheduler();
+        let runs = Runs::default();
+        let recorded = runs.0.clone();
+        let release = Arc::new(tokio::sync::Notify::new());
+        let released = release.clone();
+        scheduler.add(
+            Job::new_async_with_context(
+                Interval::new(std::time::Duration::from_secs(60)),
+                move |context| {
+                    let first = context.scheduled == at(60);
+                    recorded.lock().unwrap().push(context);
+                    let released = released.clone();
+                    async move {
+                        if first {
+                            released.notified().await;
+                        }
+                    }
+                },
+            )
+            .with_start_policy(StartPolicy::Added)
+            .with_overlap_policy(OverlapPolicy::Queue),
+        );
+        clock.set(at(60));
+        scheduler.async_tick().await;
+        tokio::task::yield_now().await;
+        clock.set(at(120));
+        scheduler.async_tick().await;
+
+        clock.set(at(130));
+        release.notify_one();
+        scheduler.env.tracker.idle().await;
+        let started: Vec<_> = runs
+            .take()
+            .into_iter()
+            .map(|run| (run.scheduled, run.started))
+            .collect();
+        assert_eq!(started, [(at(60), at(60)), (at(120), at(130))]);
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn overlap_policies_decide_on_runs_due_while_one_is_in_flight() {
+        use crate::policy::OverlapDecision::{Parallel, Queued, Replaced, Skipped};
+
+        let cases = [
+            (
+                OverlapPolicy::Skip,
+                [Skipped, Skipped],
+                vec![at(60)],
+                vec![at(60)],
+            ),
+            (
+                OverlapPolicy::Queue,
+                [Queued, Queued],
+                vec![at(60)],
+                vec![at(60), at(120), at(180)],
+            ),
+            (
+                OverlapPolicy::Allow(2),
+                [Parallel, Skipped],
+                vec![at(60), at(120)],
+                vec![at(60), at(120)],
+            ),
+            (
+                OverlapPolicy::Replace,
+                [Replaced, Replaced],
+                vec![at(60), at(120), at(180)],
+                vec![at(180)],
+            ),
+        ];
+        for (overlap, decisions, begun, finished) in cases {
+            let (mut scheduler, clock) = scheduler();
+            let decided = Arc::new(Mutex::new(Vec::new()));
+            let observed = decided.clone();
+            scheduler.add_observer(move |event: &JobEvent| {
+                if let JobEvent::Overlap { decision, .. } = event {
+                    observed.lock().unwrap().push(*decision);
+                }
+            });
+            // Runs hold on until the gate lets them finish, so that the
+            // later ones are due while the first is in flight.
+            let gate = Arc::new(tokio::sync::Semaphore::new(0));
+            let (begun_runs, finished_runs) = (Runs::default(), Runs::default());
+            let job = {
+                let (begun, finished, gate) =
+                    (begun_runs.0.clone(), finished_runs.0.clone(), gate.clone());
+                Job::new_async_with_context(
+                    Interval::new(std::time::Duration::from_secs(60)),
+                    move |context| {
+                        begun.lock().unwrap().push(context.clone());
+                        let (finished, gate) = (finished.clone(), gate.clone());
+                        async move {
+                            gate.acquire().await.unwrap().forget();
+                            finished.lock().unwrap().push(context);
+                        }
+                    },
+                )
+            };
+            scheduler.add(
+                job.with_start_policy(StartPolicy::Added)
+                    .with_overlap_policy(overlap),
+            );
+
+            for secs in [60, 120, 180] {
+                clock.set(at(secs));
+                scheduler.async_tick().await;
+                tokio::task::yield_now().await;
+            }
+            assert_eq!(*decided.lock().unwrap(), decisions, "{overlap:?}");
+            assert_eq!(begun_runs.scheduled(), begun, "{overlap:?}");
+
+            gate.add_permits(3);
+            scheduler.env.tracker.idle().await;
+            assert_eq!(finished_runs.scheduled(), finished, "{overlap:?}");
+        }
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn timed_out_runs_are_reported_and_fail() {
+        let (mut scheduler, _clock) = scheduler();
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let observed = events.clone();
+        scheduler
+            .add_observer(move |event: &JobEvent| observed.lock().unwrap().push(event.clone()));
+        let timeout = std::time::Duration::from_millis(20);
+        let id = scheduler.add(
+            Job::new_async(
+                Once::after(std::time::Duration::ZERO),
+                std::future::pending::<()>,
+            )
+            .with_timeout(timeout),
+        );
+        scheduler.async_tick().await;
+        scheduler.env.tracker.idle().await;
+
+        assert!(matches!(
+            events.lock().unwrap()[..],
+            [
+                JobEvent::TimedOut { job, attempt: 1 },
+                JobEvent::Failed { job: failed, attempt: 1, .. },
+            ] if job == id && failed == id
+        ));
+        let error = scheduler.get(id).unwrap().last_error().unwrap();
+        assert_eq!(error.downcast_ref::<TimedOut>(), Some(&TimedOut(timeout)));
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn time_queued_behind_earlier_runs_does_not_count_towards_the_timeout() {
+        let (mut scheduler, clock) = scheduler();
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let observed = events.clone();
+        scheduler
+            .add_observer(move |event: &JobEvent| observed.lock().unwrap().push(event.clone()));
+        let run = std::time::Duration::from_millis(120);
+        let id = scheduler.add(
+            Job::new_async(
+                Interval::new(std::time::Duration::from_secs(60)),
+                move || tokio::time::sleep(run),
+            )
+            .with_start_policy(StartPolicy::Added)
+            .with_overlap_policy(OverlapPolicy::Queue)
+            .with_timeout(std::time::Duration::from_millis(200)),
+        );
+        for secs in [60, 120] {
+            clock.set(at(secs));
+            scheduler.async_tick().await;
+        }
+        scheduler.env.tracker.idle().await;
+
+        assert!(matches!(
+            events.lock().unwrap()[..],
+            [JobEvent::Overlap { .. }]
+        ));
+        assert_eq!(scheduler.get(id).unwrap().failures(), 0);
+    }
+}
@@ -0,0 +1,151 @@
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use chrono::{DateTime, Utc};
+use serde_json::{Map, Value};
+
+/// Persists the last tick of named jobs so that runs missed while the process
+/// was down are caught up on restart, according to each job's misfire policy.
+pub trait StateStore: Send + Sync + 'static {
+    fn load(&self, job: &str) -> io::Result<Option<DateTime<Utc>>>;
+
+    fn save(&self, job: &str, last_tick: DateTime<Utc>) -> io::Result<()>;
+
+    /// Saves the last ticks of several jobs, which a scheduler does for all
+    /// the jobs a tick moved. Saves each in turn by default; stores that can
+    /// write them together should override this.
+    fn save_all(&self, ticks: &[(&str, DateTime<Utc>)]) -> io::Result<()> {
+        ticks
+            .iter()
+            .try_for_each(|&(job, last_tick)| self.save(job, last_tick))
+    }
+}
+
+/// A `StateStore` keeping every job's last tick in one JSON object, keyed by
+/// job name, with RFC 3339 timestamps as values.
+pub struct JsonFileStore {
+    path: PathBuf,
+    ticks: Mutex<BTreeMap<String, DateTime<Utc>>>,
+}
+
+impl JsonFileStore {
+    /// Opens the store at `path`. A missing file is treated as empty and is
+    /// created on the first save.
+    pub fn open(path: impl AsRef<Path>) -> io::Result<JsonFileStore> {
+        let path = path.as_ref().to_path_buf();
+        let ticks = match fs::read(&path) {
+            Ok(bytes) => parse(&bytes)?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(e) => return Err(e),
+        };
+        Ok(JsonFileStore {
+            path,
+            ticks: Mutex::new(ticks),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl StateStore for JsonFileStore {
+    fn load(&self, job: &str) -> io::Result<Option<DateTime<Utc>>> {
+        Ok(self.ticks.lock().unwrap().get(job).copied())
+    }
+
+    fn save(&self, job: &str, last_tick: DateTime<Utc>) -> io::Result<()> {
+        self.save_all(&[(job, last_tick)])
+    }
+
+    /// Rewrites the file once for all of `ticks`.
+    fn save_all(&self, ticks: &[(&str, DateTime<Utc>)]) -> io::Result<()> {
+        let mut saved = self.ticks.lock().unwrap();
+        for &(job, last_tick) in ticks {
+            saved.insert(job.to_string(), last_tick);
+        }
+
+        let object: Map<String, Value> = saved
+            .iter()
+            .map(|(job, tick)| (job.clone(), Value::String(tick.to_rfc3339())))
+            .collect();
+        let bytes = serde_json::to_vec_pretty(&object)?;
+
+        // Write to a sibling file first so a crash never leaves a torn file,
+        // and flush it to disk so that the rename cannot land before it.
+        let mut tmp = self.path.clone().into_os_string();
+        tmp.push(".tmp");
+        let mut file = File::create(&tmp)?;
+        file.write_all(&bytes)?;
+        file.sync_all()?;
+        fs::rename(&tmp, &self.path)
+    }
+}
+
+fn parse(bytes: &[u8]) -> io::Result<BTreeMap<String, DateTime<Utc>>> {
+    let invalid = |msg: String| io::Error::new(io::ErrorKind::InvalidData, msg);
+
+    let object: Map<String, Value> = serde_json::from_slice(bytes)?;
+    object
+        .into_iter()
+        .map(|(job, value)| {
+            let tick = value
+                .as_str()
+                .ok_or_else(|| invalid(format!("last tick of job {job:?} is not a string")))?;
+            let tick = DateTime::parse_from_rfc3339(tick)
+                .map_err(|e| invalid(format!("last tick of job {job:?}: {e}")))?;
+            Ok((job, tick.with_timezone(&Utc)))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::at;
+
+    /// A file path in the temp dir, unique to this test process, with no file
+    /// at it yet.
+    fn temp_path(name: &str) -> PathBuf {
+        let file = format!("job_sched-{}-{name}.json", std::process::id());
+        let path = std::env::temp_dir().join(file);
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    #[test]
+    fn saved_ticks_survive_reopening() {
+        let path = temp_path("reopen");
+        let store = JsonFileStore::open(&path).unwrap();
+        assert_eq!(store.load("report").unwrap(), None);
+        store
+            .save_all(&[("report", at(60)), ("backup", at(120))])
+            .unwrap();
+        store.save("report", at(180)).unwrap();
+
+        let reopened = JsonFileStore::open(&path).unwrap();
+        assert_eq!(reopened.load("report").unwrap(), Some(at(180)));
+        assert_eq!(reopened.load("backup").unwrap(), Some(at(120)));
+        let object: Map<String, Value> = serde_json::from_slice(&fs::read(&path).unwrap()).unwrap();
+        assert_eq!(object["backup"], at(120).to_rfc3339());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn malformed_files_are_refused() {
+        for (name, contents) in [
+            ("not-an-object", "[]"),
+            ("not-a-string", r#"{"report": 60}"#),
+            ("not-a-time", r#"{"report": "soon"}"#),
+        ] {
+            let path = temp_path(name);
+            fs::write(&path, contents).unwrap();
+            let error = JsonFileStore::open(&path).err().unwrap();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidData, "{name}");
+            fs::remove_file(&path).unwrap();
+        }
+    }
+}
@@ -0,0 +1,11 @@
+use chrono::{DateTime, TimeZone, Utc};
+
+/// The instant tests start their clocks at.
+pub(crate) fn start() -> DateTime<Utc> {
+    Utc.with_ymd_and_hms(2030, 1, 1, 0, 0, 0).unwrap()
+}
+
+/// `secs` seconds after `start()`.
+pub(crate) fn at(secs: i64) -> DateTime<Utc> {
+    start() + chrono::Duration::seconds(secs)
+}
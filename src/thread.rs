@@ -0,0 +1,115 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::thread::{self, JoinHandle};
+
+use crate::job::{Job, JobId};
+use crate::scheduler::JobScheduler;
+use crate::wakeup::Wakeup;
+
+/// Drives a scheduler's sync and blocking jobs on a dedicated thread, without
+/// an async runtime. Async jobs are not run: they wait for an `async_tick`
+/// that the runner never makes.
+pub struct ThreadRunner {
+    scheduler: Arc<Mutex<JobScheduler>>,
+    stopped: Arc<AtomicBool>,
+    wakeup: Arc<Wakeup>,
+    thread: JoinHandle<()>,
+}
+
+impl ThreadRunner {
+    pub fn add(&self, job: Job) -> JobId {
+        self.lock().add(job)
+    }
+
+    pub fn remove(&self, id: JobId) -> Option<Job> {
+        self.lock().remove(id)
+    }
+
+    /// Locks the scheduler. The runner thread is blocked while the guard is
+    /// held. A job that panics stops the runner thread, but the scheduler
+    /// can still be locked.
+    pub fn lock(&self) -> MutexGuard<'_, JobScheduler> {
+        self.scheduler
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Stops the runner, waiting for the job it is running to finish, and
+    /// returns the scheduler. If a job panicked on the runner thread, the
+    /// panic is resumed here.
+    pub fn shutdown(self) -> JobScheduler {
+        self.stopped.store(true, Ordering::SeqCst);
+        self.wakeup.wake();
+        if let Err(panic) = self.thread.join() {
+            std::panic::resume_unwind(panic);
+        }
+        match Arc::try_unwrap(self.scheduler) {
+            Ok(scheduler) => scheduler
+                .into_inner()
+                .unwrap_or_else(PoisonError::into_inner),
+            Err(_) => unreachable!("the runner thread has exited"),
+        }
+    }
+}
+
+impl JobScheduler {
+    /// Drives the scheduler on a new thread, sleeping until the next due
+    /// event between ticks.
+    pub fn spawn_thread(self) -> ThreadRunner {
+        let wakeup = self.env.wakeup.clone();
+        let scheduler = Arc::new(Mutex::new(self));
+        let stopped = Arc::new(AtomicBool::new(false));
+
+        let thread = {
+            let scheduler = scheduler.clone();
+            let stopped = stopped.clone();
+            let wakeup = wakeup.clone();
+            thread::spawn(move || {
+                while !stopped.load(Ordering::SeqCst) {
+                    let sleep = {
+                        let mut scheduler = scheduler.lock().unwrap();
+                        scheduler.tick();
+                        scheduler.next_job().map(|(_, _, wait)| wait)
+                    };
+                    wakeup.wait(sleep);
+                }
+            })
+        };
+
+        ThreadRunner {
+            scheduler,
+            stopped,
+            wakeup,
+            thread,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::panic::{self, AssertUnwindSafe};
+    use std::time::Duration;
+
+    use super::*;
+    use crate::trigger::Once;
+
+    #[test]
+    fn a_job_panic_stops_the_runner_and_is_resumed_on_shutdown() {
+        let runner = JobScheduler::new().spawn_thread();
+        let id = runner.add(Job::new::<_, _, ()>(Once::after(Duration::ZERO), || {
+            panic!("job failed")
+        }));
+        while !runner.thread.is_finished() {
+            thread::yield_now();
+        }
+        assert!(runner.lock().contains(id));
+
+        let other = runner.add(Job::new(Once::after(Duration::from_secs(60)), || {}));
+        assert!(runner.remove(other).is_some());
+
+        let Err(panic) = panic::catch_unwind(AssertUnwindSafe(|| runner.shutdown())) else {
+            panic!("shutdown did not resume the job's panic");
+        };
+        assert_eq!(panic.downcast_ref::<&str>(), Some(&"job failed"));
+    }
+}
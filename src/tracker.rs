@@ -0,0 +1,141 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::{Arc, Mutex};
+
+use tokio::sync::Notify;
+use tokio::task::JoinHandle;
+
+use crate::job::JobId;
+use crate::wakeup::Wakeup;
+
+/// Keeps track of the job runs currently in flight. Once closed, no new run
+/// may begin.
+#[derive(Default)]
+pub(crate) struct RunTracker {
+    state: Mutex<State>,
+    changed: Notify,
+}
+
+#[derive(Default)]
+struct State {
+    closed: bool,
+    next_run: u64,
+    runs: BTreeMap<u64, Entry>,
+}
+
+struct Entry {
+    job: JobId,
+    task: Option<JoinHandle<()>>,
+}
+
+impl RunTracker {
+    /// Registers a run of `job`, or returns `None` if the tracker is closed.
+    pub(crate) fn begin(self: &Arc<Self>, job: JobId) -> Option<RunGuard> {
+        let mut state = self.state.lock().unwrap();
+        if state.closed {
+            return None;
+        }
+        let run = state.next_run;
+        state.next_run += 1;
+        state.runs.insert(run, Entry { job, task: None });
+        Some(RunGuard {
+            tracker: self.clone(),
+            run,
+        })
+    }
+
+    /// Spawns `fut` as a tracked run of `job` on the tokio runtime, notifying
+    /// `wake` once the run is no longer tracked.
+    pub(crate) fn spawn<F>(self: &Arc<Self>, job: JobId, fut: F, wake: Arc<Wakeup>) -> bool
+    where
+        F: std::future::Future<Output = ()> + Send + 'static,
+    {
+        let guard = match self.begin(job) {
+            Some(guard) => guard,
+            None => return false,
+        };
+        let run = guard.run;
+        let task = tokio::spawn(async move {
+            fut.await;
+            drop(guard);
+            wake.wake();
+        });
+        // The run may already be over, in which case there is nothing to keep.
+        if let Some(entry) = self.state.lock().unwrap().runs.get_mut(&run) {
+            entry.task = Some(task);
+        }
+        true
+    }
+
+    pub(crate) fn close(&self) {
+        self.state.lock().unwrap().closed = true;
+        self.changed.notify_waiters();
+    }
+
+    pub(crate) fn is_closed(&self) -> bool {
+        self.state.lock().unwrap().closed
+    }
+
+    pub(crate) fn running(&self) -> Vec<JobId> {
+        let state = self.state.lock().unwrap();
+        let jobs: BTreeSet<JobId> = state.runs.values().map(|entry| entry.job).collect();
+        jobs.into_iter().collect()
+    }
+
+    pub(crate) fn running_count(&self, job: JobId) -> usize {
+        let state = self.state.lock().unwrap();
+        state.runs.values().filter(|entry| entry.job == job).count()
+    }
+
+    /// Cancels the spawned runs of `job` that are still in flight.
+    pub(crate) fn abort_job(&self, job: JobId) {
+        let state = self.state.lock().unwrap();
+        for entry in state.runs.values().filter(|entry| entry.job == job) {
+            if let Some(task) = &entry.task {
+                task.abort();
+            }
+        }
+    }
+
+    /// Cancels every spawned run that is still in flight.
+    pub(crate) fn abort_all(&self) {
+        for entry in self.state.lock().unwrap().runs.values() {
+            if let Some(task) = &entry.task {
+                task.abort();
+            }
+        }
+    }
+
+    pub(crate) async fn closed(&self) {
+        loop {
+            let changed = self.changed.notified();
+            if self.is_closed() {
+                return;
+            }
+            changed.await;
+        }
+    }
+
+    pub(crate) async fn idle(&self) {
+        loop {
+            let changed = self.changed.notified();
+            if self.state.lock().unwrap().runs.is_empty() {
+                return;
+            }
+            changed.await;
+        }
+    }
+}
+
+pub(crate) struct RunGuard {
+    tracker: Arc<RunTracker>,
+    run: u64,
+}
+
+impl Drop for RunGuard {
+    fn drop(&mut self) {
+        let entry = self.tracker.state.lock().unwrap().runs.remove(&self.run);
+        // Dropped outside the lock: the entry may own the run's own JoinHandle.
+        drop(entry);
+        self.tracker.changed.notify_waiters();
+    }
+}
@@ -0,0 +1,410 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::{DateTime, LocalResult, NaiveDateTime, Offset, TimeZone, Utc};
+use cron::Schedule;
+
+use crate::clock::saturating_add;
+
+/// Decides when a job fires.
+pub trait Trigger: Send + Sync + 'static {
+    /// The first fire time strictly after `after`, or `None` if the trigger
+    /// never fires again.
+    fn next_after(&self, after: &DateTime<Utc>) -> Option<DateTime<Utc>>;
+
+    /// Called with the scheduler's current time when the job is added.
+    fn start(&mut self, _now: DateTime<Utc>) {}
+
+    /// Whether events are counted from when the job is added, rather than
+    /// from its first tick, under `StartPolicy::FirstTick`. One-shot
+    /// triggers need this so that a late first tick cannot pass over their
+    /// only event.
+    fn counts_from_add(&self) -> bool {
+        false
+    }
+}
+
+/// A cron schedule evaluated in UTC.
+impl Trigger for Schedule {
+    fn next_after(&self, after: &DateTime<Utc>) -> Option<DateTime<Utc>> {
+        self.after(after).next()
+    }
+}
+
+/// A cron schedule evaluated in a given time zone, so that e.g. "9:00 every
+/// weekday" follows local wall-clock time across DST changes. A local time
+/// that occurs twice, when clocks go back, fires at its earlier occurrence
+/// only. A local time that is skipped, when clocks go forward, fires as far
+/// past the change as it was into the gap, so 2:30 in a 2:00 to 3:00 gap
+/// fires at 3:30.
+#[derive(Clone)]
+pub struct CronTrigger {
+    schedule: Schedule,
+    timezone: Arc<dyn Zone>,
+}
+
+impl CronTrigger {
+    pub fn new(schedule: Schedule) -> CronTrigger {
+        CronTrigger {
+            schedule,
+            timezone: Arc::new(Utc),
+        }
+    }
+
+    /// Evaluates the schedule in `timezone` instead of UTC. Any
+    /// `chrono::TimeZone` works, including `chrono_tz::Tz`.
+    pub fn with_timezone<Tz>(mut self, timezone: Tz) -> CronTrigger
+    where
+        Tz: TimeZone + Send + Sync + 'static,
+        Tz::Offset: Send + Sync,
+    {
+        self.timezone = Arc::new(timezone);
+        self
+    }
+
+    pub fn schedule(&self) -> &Schedule {
+        &self.schedule
+    }
+}
+
+impl From<Schedule> for CronTrigger {
+    fn from(schedule: Schedule) -> CronTrigger {
+        CronTrigger::new(schedule)
+    }
+}
+
+impl Trigger for CronTrigger {
+    fn next_after(&self, after: &DateTime<Utc>) -> Option<DateTime<Utc>> {
+        self.timezone.next_after(&self.schedule, after)
+    }
+}
+
+/// A time zone erased so that cron triggers in different zones share a type.
+trait Zone: Send + Sync {
+    fn next_after(&self, schedule: &Schedule, after: &DateTime<Utc>) -> Option<DateTime<Utc>>;
+}
+
+impl<Tz> Zone for Tz
+where
+    Tz: TimeZone + Send + Sync,
+    Tz::Offset: Send + Sync,
+{
+    fn next_after(&self, schedule: &Schedule, after: &DateTime<Utc>) -> Option<DateTime<Utc>> {
+        // The schedule is walked over naive local times, read as UTC so that
+        // none of them is dropped for being ambiguous or missing; each one
+        // is then resolved in the zone. Local times that repeat map back to
+        // instants at or before `after` and are passed over.
+        let local = after.with_timezone(self).naive_local().and_utc();
+        schedule
+            .after(&local)
+            .map_while(|event| resolve(self, event.naive_utc()))
+            .find(|event| event > after)
+    }
+}
+
+/// The instant at which `local` is read in `timezone`, or `None` if it is out
+/// of range.
+fn resolve<Tz: TimeZone>(timezone: &Tz, local: NaiveDateTime) -> Option<DateTime<Utc>> {
+    let offset = match timezone.offset_from_local_datetime(&local) {
+        LocalResult::Single(offset) | LocalResult::Ambiguous(offset, _) => offset.fix(),
+        // In a gap, read the time with the offset from before it. Offset
+        // changes are months apart, so the offset a day earlier is that one.
+        LocalResult::None => {
+            let day_before = local.checked_sub_signed(chrono::Duration::days(1))?;
+            timezone.offset_from_utc_datetime(&day_before).fix()
+        }
+    };
+    Some(local.checked_sub_offset(offset)?.and_utc())
+}
+
+/// Fires every `period`, at whole multiples of it from an anchor time. The
+/// anchor defaults to the Unix epoch, which aligns e.g. a 15 minute period to
+/// the quarter hour. A zero period never fires.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Interval {
+    period: Duration,
+    anchor: DateTime<Utc>,
+}
+
+impl Interval {
+    pub fn new(period: Duration) -> Interval {
+        Interval {
+            period,
+            anchor: DateTime::UNIX_EPOCH,
+        }
+    }
+
+    /// Fires at `anchor` and every `period` before and after it.
+    pub fn with_anchor(mut self, anchor: DateTime<Utc>) -> Interval {
+        self.anchor = anchor;
+        self
+    }
+
+    pub fn period(&self) -> Duration {
+        self.period
+    }
+
+    pub fn anchor(&self) -> DateTime<Utc> {
+        self.anchor
+    }
+}
+
+impl Trigger for Interval {
+    fn next_after(&self, after: &DateTime<Utc>) -> Option<DateTime<Utc>> {
+        const NANOS_PER_SEC: i128 = 1_000_000_000;
+
+        let period = self.period.as_nanos() as i128;
+        if period == 0 {
+            return None;
+        }
+        let elapsed = *after - self.anchor;
+        let elapsed =
+            elapsed.num_seconds() as i128 * NANOS_PER_SEC + elapsed.subsec_nanos() as i128;
+        let offset = (elapsed.div_euclid(period) + 1) * period;
+
+        let secs = i64::try_from(offset.div_euclid(NANOS_PER_SEC)).ok()?;
+        let nanos = offset.rem_euclid(NANOS_PER_SEC) as i64;
+        self.anchor
+            .checked_add_signed(chrono::Duration::try_seconds(secs)?)?
+            .checked_add_signed(chrono::Duration::nanoseconds(nanos))
+    }
+}
+
+/// Fires a single time, at an instant or after a delay. A job whose trigger
+/// has fired for the last time is removed from its scheduler once its run is
+/// over.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Once {
+    at: OnceAt,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum OnceAt {
+    Instant(DateTime<Utc>),
+    Delay(Duration),
+}
+
+impl Once {
+    /// Fires at `at`. An instant before the job is added never fires.
+    pub fn at(at: DateTime<Utc>) -> Once {
+        Once {
+            at: OnceAt::Instant(at),
+        }
+    }
+
+    /// Fires `delay` after the job is added to a scheduler.
+    pub fn after(delay: Duration) -> Once {
+        Once {
+            at: OnceAt::Delay(delay),
+        }
+    }
+}
+
+impl Trigger for Once {
+    fn next_after(&self, after: &DateTime<Utc>) -> Option<DateTime<Utc>> {
+        match self.at {
+            OnceAt::Instant(at) if at > *after => Some(at),
+            _ => None,
+        }
+    }
+
+    fn start(&mut self, now: DateTime<Utc>) {
+        if let OnceAt::Delay(delay) = self.at {
+            self.at = OnceAt::Instant(saturating_add(now, delay));
+        }
+    }
+
+    fn counts_from_add(&self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{FixedOffset, NaiveDate};
+
+    use super::*;
+    use crate::test_util::start;
+
+    fn millis(millis: i64) -> chrono::Duration {
+        chrono::Duration::milliseconds(millis)
+    }
+
+    #[test]
+    fn interval_fires_before_an_anchor_ahead_of_it() {
+        let anchor = start() + chrono::Duration::hours(1) + millis(15_000);
+        let interval = Interval::new(Duration::from_secs(60)).with_anchor(anchor);
+        assert_eq!(
+            interval.next_after(&start()),
+            Some(start() + millis(15_000))
+        );
+        assert_eq!(
+            interval.next_after(&(start() + millis(15_000))),
+            Some(start() + millis(75_000))
+        );
+        assert_eq!(
+            interval.next_after(&(anchor - millis(60_001))),
+            Some(anchor - millis(60_000))
+        );
+        assert_eq!(
+            interval.next_after(&(anchor - millis(60_000))),
+            Some(anchor)
+        );
+    }
+
+    #[test]
+    fn interval_keeps_sub_second_offsets_before_its_anchor() {
+        let anchor = start() + millis(500);
+        let interval = Interval::new(Duration::from_secs(1)).with_anchor(anchor);
+        assert_eq!(
+            interval.next_after(&(start() - millis(1_000))),
+            Some(start() - millis(500))
+        );
+        assert_eq!(interval.next_after(&(start() - millis(500))), Some(anchor));
+
+        let before_epoch = DateTime::UNIX_EPOCH - millis(250);
+        let interval = Interval::new(Duration::from_millis(100)).with_anchor(before_epoch);
+        assert_eq!(
+            interval.next_after(&(before_epoch - millis(1_001))),
+            Some(before_epoch - millis(1_000))
+        );
+        assert_eq!(
+            interval.next_after(&DateTime::UNIX_EPOCH),
+            Some(DateTime::UNIX_EPOCH + millis(50))
+        );
+    }
+
+    #[test]
+    fn zero_interval_never_fires() {
+        assert_eq!(Interval::new(Duration::ZERO).next_after(&start()), None);
+    }
+
+    #[test]
+    fn once_fires_a_single_time() {
+        let once = Once::at(start());
+        assert_eq!(once.next_after(&(start() - millis(1))), Some(start()));
+        assert_eq!(once.next_after(&start()), None);
+
+        let mut once = Once::after(Duration::from_secs(10));
+        once.start(start());
+        assert_eq!(once.next_after(&start()), Some(start() + millis(10_000)));
+
+        let mut never = Once::after(Duration::MAX);
+        never.start(start());
+        assert_eq!(never.next_after(&start()), Some(DateTime::<Utc>::MAX_UTC));
+    }
+
+    /// New York in 2030: UTC-4 from 2030-03-10 07:00 UTC until 2030-11-03
+    /// 06:00 UTC, and UTC-5 otherwise.
+    #[derive(Debug, Clone, Copy)]
+    struct NewYork2030;
+
+    impl NewYork2030 {
+        fn is_dst(utc: &NaiveDateTime) -> bool {
+            let from = NaiveDate::from_ymd_opt(2030, 3, 10)
+                .unwrap()
+                .and_hms_opt(7, 0, 0);
+            let until = NaiveDate::from_ymd_opt(2030, 11, 3)
+                .unwrap()
+                .and_hms_opt(6, 0, 0);
+            (from.unwrap()..until.unwrap()).contains(utc)
+        }
+    }
+
+    impl TimeZone for NewYork2030 {
+        type Offset = FixedOffset;
+
+        fn from_offset(_offset: &FixedOffset) -> NewYork2030 {
+            NewYork2030
+        }
+
+        fn offset_from_local_date(&self, local: &NaiveDate) -> LocalResult<FixedOffset> {
+            self.offset_from_local_datetime(&local.and_hms_opt(0, 0, 0).unwrap())
+        }
+
+        fn offset_from_local_datetime(&self, local: &NaiveDateTime) -> LocalResult<FixedOffset> {
+            let offsets: Vec<_> = [-4, -5]
+                .into_iter()
+                .map(|hours| FixedOffset::east_opt(hours * 3600).unwrap())
+                .filter(|offset| {
+                    let utc = *local - *offset;
+                    self.offset_from_utc_datetime(&utc) == *offset
+                })
+                .collect();
+            match offsets[..] {
+                [] => LocalResult::None,
+                [offset] => LocalResult::Single(offset),
+                [earliest, latest] => LocalResult::Ambiguous(earliest, latest),
+                _ => unreachable!(),
+            }
+        }
+
+        fn offset_from_utc_date(&self, utc: &NaiveDate) -> FixedOffset {
+            self.offset_from_utc_datetime(&utc.and_hms_opt(0, 0, 0).unwrap())
+        }
+
+        fn offset_from_utc_datetime(&self, utc: &NaiveDateTime) -> FixedOffset {
+            let hours = if NewYork2030::is_dst(utc) { -4 } else { -5 };
+            FixedOffset::east_opt(hours * 3600).unwrap()
+        }
+    }
+
+    fn utc(month: u32, day: u32, hour: u32, minute: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2030, month, day, hour, minute, 0)
+            .unwrap()
+    }
+
+    fn cron(expression: &str) -> CronTrigger {
+        CronTrigger::new(expression.parse().unwrap()).with_timezone(NewYork2030)
+    }
+
+    #[test]
+    fn cron_follows_local_time_across_dst_changes() {
+        let nine = cron("0 0 9 * * *");
+        assert_eq!(nine.next_after(&utc(3, 9, 15, 0)), Some(utc(3, 10, 13, 0)));
+        assert_eq!(nine.next_after(&utc(11, 2, 14, 0)), Some(utc(11, 3, 14, 0)));
+    }
+
+    #[test]
+    fn cron_fires_repeated_local_times_once_at_the_earlier_instant() {
+        let half_past_one = cron("0 30 1 * * *");
+        assert_eq!(
+            half_past_one.next_after(&utc(11, 2, 12, 0)),
+            Some(utc(11, 3, 5, 30))
+        );
+        assert_eq!(
+            half_past_one.next_after(&utc(11, 3, 5, 30)),
+            Some(utc(11, 4, 6, 30))
+        );
+
+        // The second 1:15 does not fire 1:30 again, which has passed.
+        let quarters = cron("0 */15 * * * *");
+        assert_eq!(
+            quarters.next_after(&utc(11, 3, 6, 15)),
+            Some(utc(11, 3, 7, 0))
+        );
+    }
+
+    #[test]
+    fn cron_fires_skipped_local_times_past_the_gap() {
+        let half_past_two = cron("0 30 2 * * *");
+        assert_eq!(
+            half_past_two.next_after(&utc(3, 9, 12, 0)),
+            Some(utc(3, 10, 7, 30))
+        );
+        assert_eq!(
+            half_past_two.next_after(&utc(3, 10, 7, 30)),
+            Some(utc(3, 11, 6, 30))
+        );
+
+        let quarters = cron("0 */15 * * * *");
+        assert_eq!(
+            quarters.next_after(&utc(3, 10, 6, 45)),
+            Some(utc(3, 10, 7, 0))
+        );
+        assert_eq!(
+            quarters.next_after(&utc(3, 10, 7, 0)),
+            Some(utc(3, 10, 7, 15))
+        );
+    }
+}
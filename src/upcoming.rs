@@ -0,0 +1,131 @@
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BinaryHeap};
+
+use chrono::{DateTime, Utc};
+
+use crate::job::{Job, JobId};
+use crate::scheduler::JobScheduler;
+
+/// The scheduled events of several jobs, merged in time order.
+struct Upcoming<'a> {
+    jobs: &'a BTreeMap<JobId, Job>,
+    next: BinaryHeap<Reverse<(DateTime<Utc>, JobId)>>,
+}
+
+impl<'a> Upcoming<'a> {
+    /// The events of `scheduler`'s jobs after `after`. Paused jobs have none,
+    /// nor does a paused scheduler.
+    fn after(scheduler: &'a JobScheduler, after: DateTime<Utc>) -> Upcoming<'a> {
+        let jobs = &scheduler.jobs;
+        let next = jobs
+            .iter()
+            .filter(|(_, job)| !scheduler.is_paused() && !job.is_paused())
+            .filter_map(|(&id, job)| Some(Reverse((job.next_event_after(&after)?, id))))
+            .collect();
+        Upcoming { jobs, next }
+    }
+}
+
+impl Iterator for Upcoming<'_> {
+    type Item = (JobId, DateTime<Utc>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let Reverse((at, id)) = self.next.pop()?;
+        if let Some(next) = self.jobs[&id].next_event_after(&at) {
+            self.next.push(Reverse((next, id)));
+        }
+        Some((id, at))
+    }
+}
+
+impl JobScheduler {
+    /// The next `count` scheduled events across all jobs, in time order.
+    /// Retries, manual runs and paused jobs are left out, and nothing is
+    /// listed while the scheduler is paused.
+    pub fn upcoming(&self, count: usize) -> Vec<(JobId, DateTime<Utc>)> {
+        let now = self.env.clock.now();
+        Upcoming::after(self, now).take(count).collect()
+    }
+
+    /// The scheduled events after `after` up to and including `until`,
+    /// across all jobs and in time order. Retries, manual runs and paused
+    /// jobs are left out, and nothing is listed while the scheduler is
+    /// paused.
+    pub fn upcoming_between(
+        &self,
+        after: DateTime<Utc>,
+        until: DateTime<Utc>,
+    ) -> Vec<(JobId, DateTime<Utc>)> {
+        Upcoming::after(self, after)
+            .take_while(|&(_, at)| at <= until)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::clock::ManualClock;
+    use crate::test_util::{at, start};
+    use crate::trigger::{Interval, Once};
+
+    fn scheduler() -> JobScheduler {
+        JobScheduler::new().with_clock(ManualClock::new(start()))
+    }
+
+    fn every(secs: u64) -> Job {
+        Job::new(Interval::new(Duration::from_secs(secs)), || {})
+    }
+
+    #[test]
+    fn upcoming_merges_the_events_of_all_jobs_in_time_order() {
+        let mut scheduler = scheduler();
+        let minutely = scheduler.add(every(60));
+        let once = scheduler.add(Job::new(Once::after(Duration::from_secs(90)), || {}));
+        let half_minutely = scheduler.add(every(30));
+
+        assert_eq!(
+            scheduler.upcoming(5),
+            [
+                (half_minutely, at(30)),
+                (minutely, at(60)),
+                (half_minutely, at(60)),
+                (once, at(90)),
+                (half_minutely, at(90)),
+            ]
+        );
+        assert_eq!(scheduler.upcoming(1), [(half_minutely, at(30))]);
+        assert!(scheduler.upcoming(0).is_empty());
+    }
+
+    #[test]
+    fn upcoming_between_leaves_out_after_and_keeps_until() {
+        let mut scheduler = scheduler();
+        let id = scheduler.add(every(60));
+
+        assert_eq!(
+            scheduler.upcoming_between(at(60), at(180)),
+            [(id, at(120)), (id, at(180))]
+        );
+        assert!(scheduler.upcoming_between(at(60), at(119)).is_empty());
+        assert_eq!(scheduler.upcoming_between(at(-1), at(0)), [(id, at(0))]);
+    }
+
+    #[test]
+    fn nothing_is_upcoming_for_paused_jobs_or_schedulers() {
+        let mut scheduler = scheduler();
+        let paused = scheduler.add(every(60));
+        let running = scheduler.add(every(60));
+        assert!(scheduler.pause_job(paused));
+        assert_eq!(scheduler.upcoming(1), [(running, at(60))]);
+
+        scheduler.pause();
+        assert!(scheduler.upcoming(1).is_empty());
+        assert!(scheduler.upcoming_between(start(), at(600)).is_empty());
+
+        scheduler.resume();
+        assert_eq!(scheduler.upcoming(1), [(running, at(60))]);
+    }
+}
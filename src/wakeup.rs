@@ -0,0 +1,41 @@
+use std::sync::{Condvar, Mutex};
+use std::time::Duration;
+
+/// Wakes a run loop early, e.g. when jobs are added or a retry is scheduled.
+/// A wake-up that arrives while the loop is busy is kept for its next wait.
+#[derive(Default)]
+pub(crate) struct Wakeup {
+    woken: Mutex<bool>,
+    cond: Condvar,
+    #[cfg(feature = "tokio")]
+    notify: tokio::sync::Notify,
+}
+
+impl Wakeup {
+    pub(crate) fn wake(&self) {
+        *self.woken.lock().unwrap() = true;
+        self.cond.notify_all();
+        #[cfg(feature = "tokio")]
+        self.notify.notify_one();
+    }
+
+    /// Blocks the thread until woken or until `timeout`, if any, has passed.
+    pub(crate) fn wait(&self, timeout: Option<Duration>) {
+        let woken = self.woken.lock().unwrap();
+        let mut woken = match timeout {
+            Some(timeout) => {
+                self.cond
+                    .wait_timeout_while(woken, timeout, |woken| !*woken)
+                    .unwrap()
+                    .0
+            }
+            None => self.cond.wait_while(woken, |woken| !*woken).unwrap(),
+        };
+        *woken = false;
+    }
+
+    #[cfg(feature = "tokio")]
+    pub(crate) async fn woken(&self) {
+        self.notify.notified().await
+    }
+}
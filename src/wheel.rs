@@ -0,0 +1,288 @@
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+
+use crate::job::JobId;
+
+const NANOS_PER_SEC: i128 = 1_000_000_000;
+const SLOT_BITS: u32 = 6;
+const SLOTS: usize = 1 << SLOT_BITS;
+const LEVELS: usize = 6;
+
+type Entry = (DateTime<Utc>, JobId);
+
+/// A hashed hierarchical timing wheel. Times are rounded up to the next
+/// multiple of the resolution, counted in ticks from the UNIX epoch. Level
+/// `n` holds the entries due within `64^(n + 1)` ticks, in slots of `64^n`
+/// ticks; when time reaches a slot its entries move down a level, or are due.
+pub(crate) struct TimingWheel {
+    resolution: i128,
+    /// The tick all slots are relative to. Never ahead of the last `now`;
+    /// unset until the wheel first sees the time.
+    elapsed: Option<u64>,
+    levels: Vec<Level>,
+    /// Entries that were due by `elapsed` when inserted, or were inserted
+    /// before it was set.
+    ready: Vec<Entry>,
+    /// Entries too far ahead for the top level.
+    far: Vec<Entry>,
+    len: usize,
+}
+
+struct Level {
+    occupied: u64,
+    slots: Vec<Vec<Entry>>,
+}
+
+impl TimingWheel {
+    pub(crate) fn new(resolution: Duration) -> TimingWheel {
+        TimingWheel {
+            resolution: resolution.as_nanos().max(1) as i128,
+            elapsed: None,
+            levels: (0..LEVELS)
+                .map(|_| Level {
+                    occupied: 0,
+                    slots: vec![Vec::new(); SLOTS],
+                })
+                .collect(),
+            ready: Vec::new(),
+            far: Vec::new(),
+            len: 0,
+        }
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.len
+    }
+
+    fn nanos(&self, at: DateTime<Utc>) -> i128 {
+        let since = at.signed_duration_since(DateTime::UNIX_EPOCH);
+        since.num_seconds() as i128 * NANOS_PER_SEC + since.subsec_nanos() as i128
+    }
+
+    /// The first tick at or after `at`.
+    fn tick_of(&self, at: DateTime<Utc>) -> u64 {
+        let nanos = self.nanos(at);
+        let tick =
+            nanos.div_euclid(self.resolution) + (nanos.rem_euclid(self.resolution) > 0) as i128;
+        tick.clamp(0, u64::MAX as i128) as u64
+    }
+
+    /// The last tick at or before `now`.
+    fn tick_before(&self, now: DateTime<Utc>) -> u64 {
+        let tick = self.nanos(now).div_euclid(self.resolution);
+        tick.clamp(0, u64::MAX as i128) as u64
+    }
+
+    /// When the tick of `at` is reached.
+    fn tick_time(&self, at: DateTime<Utc>) -> DateTime<Utc> {
+        let nanos = self.tick_of(at) as i128 * self.resolution;
+        let secs = nanos.div_euclid(NANOS_PER_SEC);
+        let subsec = nanos.rem_euclid(NANOS_PER_SEC) as u32;
+        i64::try_from(secs)
+            .ok()
+            .and_then(|secs| DateTime::from_timestamp(secs, subsec))
+            .unwrap_or(at)
+    }
+
+    pub(crate) fn insert(&mut self, at: DateTime<Utc>, job: JobId) {
+        self.len += 1;
+        let tick = self.tick_of(at);
+        let elapsed = match self.elapsed {
+            Some(elapsed) if tick > elapsed => elapsed,
+            _ => {
+                self.ready.push((at, job));
+                return;
+            }
+        };
+        let level = ((63 - (tick ^ elapsed).leading_zeros()) / SLOT_BITS) as usize;
+        if level >= LEVELS {
+            self.far.push((at, job));
+            return;
+        }
+        let slot = (tick >> (level as u32 * SLOT_BITS)) as usize % SLOTS;
+        let level = &mut self.levels[level];
+        level.occupied |= 1 << slot;
+        level.slots[slot].push((at, job));
+    }
+
+    /// Drops the entries `keep` rejects.
+    pub(crate) fn retain(&mut self, mut keep: impl FnMut(&Entry) -> bool) {
+        self.len = 0;
+        for entries in [&mut self.ready, &mut self.far] {
+            entries.retain(&mut keep);
+            self.len += entries.len();
+        }
+        for level in &mut self.levels {
+            for (slot, entries) in level.slots.iter_mut().enumerate() {
+                entries.retain(&mut keep);
+                self.len += entries.len();
+                if entries.is_empty() {
+                    level.occupied &= !(1 << slot);
+                }
+            }
+        }
+    }
+
+    /// Drops the entries of `entries` that `keep` rejects, keeping count.
+    fn retain_in(len: &mut usize, entries: &mut Vec<Entry>, keep: &mut impl FnMut(&Entry) -> bool) {
+        let before = entries.len();
+        entries.retain(|entry| keep(entry));
+        *len -= before - entries.len();
+    }
+
+    /// Sets the wheel going from `now` the first time it sees the time, and
+    /// moves the entries inserted before onto it.
+    fn start(&mut self, now: DateTime<Utc>) {
+        if self.elapsed.is_some() {
+            return;
+        }
+        self.elapsed = Some(self.tick_before(now));
+        let ready = std::mem::take(&mut self.ready);
+        self.len -= ready.len();
+        for (at, job) in ready {
+            self.insert(at, job);
+        }
+    }
+
+    /// The earliest occupied slot, as its level, slot and first tick.
+    fn next_slot(&self) -> Option<(usize, usize, u64)> {
+        let elapsed = self.elapsed?;
+        self.levels.iter().enumerate().find_map(|(n, level)| {
+            if level.occupied == 0 {
+                return None;
+            }
+            let slot = level.occupied.trailing_zeros() as usize;
+            let shift = n as u32 * SLOT_BITS;
+            let span = shift + SLOT_BITS;
+            let base = elapsed >> span << span;
+            Some((n, slot, base | (slot as u64) << shift))
+        })
+    }
+
+    /// The entry due first among those `valid` accepts, and when it is
+    /// reached. Rejected entries found on the way are dropped.
+    pub(crate) fn peek(
+        &mut self,
+        now: DateTime<Utc>,
+        mut valid: impl FnMut(&Entry) -> bool,
+    ) -> Option<(JobId, DateTime<Utc>)> {
+        self.start(now);
+        Self::retain_in(&mut self.len, &mut self.ready, &mut valid);
+        if let Some(&(at, job)) = self.ready.iter().min() {
+            return Some((job, at));
+        }
+        while let Some((n, slot, _)) = self.next_slot() {
+            let level = &mut self.levels[n];
+            let entries = &mut level.slots[slot];
+            Self::retain_in(&mut self.len, entries, &mut valid);
+            match entries.iter().min() {
+                Some(&(at, job)) => return Some((job, self.tick_time(at))),
+                None => level.occupied &= !(1 << slot),
+            }
+        }
+        Self::retain_in(&mut self.len, &mut self.far, &mut valid);
+        let &(at, job) = self.far.iter().min()?;
+        Some((job, self.tick_time(at)))
+    }
+
+    /// Takes the entries reached at `now` off the wheel.
+    pub(crate) fn pop_due(&mut self, now: DateTime<Utc>) -> Vec<Entry> {
+        self.start(now);
+        let mut due = Vec::new();
+        let now_tick = self.tick_before(now);
+        while let Some((n, slot, start)) = self.next_slot() {
+            if start > now_tick {
+                break;
+            }
+            let level = &mut self.levels[n];
+            level.occupied &= !(1 << slot);
+            let entries = std::mem::take(&mut level.slots[slot]);
+            self.len -= entries.len();
+            self.elapsed = Some(start);
+            for (at, job) in entries {
+                if self.tick_of(at) <= now_tick {
+                    due.push((at, job));
+                } else {
+                    self.insert(at, job);
+                }
+            }
+        }
+
+        if self.elapsed < Some(now_tick) {
+            self.elapsed = Some(now_tick);
+            // Bring entries that came within range onto the wheel.
+            let far = std::mem::take(&mut self.far);
+            self.len -= far.len();
+            for (at, job) in far {
+                self.insert(at, job);
+            }
+        }
+        let before = self.ready.len();
+        self.ready.retain(|&entry| {
+            let ready = entry.0 <= now;
+            if ready {
+                due.push(entry);
+            }
+            !ready
+        });
+        self.len -= before - self.ready.len();
+        due.sort();
+        due
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::start;
+
+    #[test]
+    fn starts_from_the_time_it_is_first_given() {
+        let now = start();
+        let mut wheel = TimingWheel::new(Duration::from_millis(1));
+        // The first entry being far ahead must not hold the wheel back.
+        wheel.insert(now + chrono::Duration::days(30), JobId(0));
+        for i in 1..100 {
+            wheel.insert(now + chrono::Duration::milliseconds(i), JobId(i as u64));
+        }
+        assert_eq!(
+            wheel.peek(now, |_| true),
+            Some((JobId(1), now + chrono::Duration::milliseconds(1)))
+        );
+        assert!(wheel.ready.is_empty());
+        assert_eq!(wheel.len(), 100);
+
+        let due = wheel.pop_due(now + chrono::Duration::milliseconds(50));
+        assert_eq!(due.len(), 50);
+        assert!(wheel.ready.is_empty());
+        assert_eq!(wheel.len(), 50);
+    }
+
+    #[test]
+    fn retain_keeps_count_and_slots() {
+        let now = start();
+        let mut wheel = TimingWheel::new(Duration::from_millis(1));
+        wheel.pop_due(now);
+        for i in 0..200 {
+            wheel.insert(
+                now + chrono::Duration::milliseconds(i * 997),
+                JobId(i as u64),
+            );
+        }
+        wheel.insert(now + chrono::Duration::days(10_000), JobId(1_001));
+        wheel.retain(|&(_, job)| job.0 % 2 == 1);
+        assert_eq!(wheel.len(), 101);
+        assert_eq!(
+            wheel.peek(now, |_| true).map(|(job, _)| job),
+            Some(JobId(1))
+        );
+
+        let due = wheel.pop_due(now + chrono::Duration::days(20_000));
+        let jobs: Vec<u64> = due.into_iter().map(|(_, job)| job.0).collect();
+        let odd: Vec<u64> = (0..200).filter(|i| i % 2 == 1).chain([1_001]).collect();
+        assert_eq!(jobs, odd);
+        assert_eq!(wheel.len(), 0);
+        assert_eq!(wheel.peek(now, |_| true), None);
+    }
+}